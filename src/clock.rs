@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+use crate::sync::lock_or_recover;
+
+const LOG_TARGET: &str = "clock";
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Source of time for the scheduler, the core system and the main loop
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    // Calendar time, for timestamps that are recorded or leave the process
+    fn system_time(&self) -> SystemTime;
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep;
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(self.now() + duration)
+    }
+
+    // Waits while nothing in the system is runnable. Only differs from
+    // `sleep` for clocks that nobody else would move forward meanwhile.
+    fn idle(&self, duration: Duration) -> Sleep {
+        self.sleep(duration)
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+// Wall-clock time, with sleeps on the tokio timer
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Box::pin(tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)))
+    }
+}
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+struct SimulatedState {
+    start: Instant,
+    now: Instant,
+    // Pending sleeps by id, each with the waker of its latest poll
+    sleepers: HashMap<u64, (Instant, Waker)>,
+    next_sleep_id: u64,
+}
+
+// Virtual time that only moves when `advance` is called. Driving a system by
+// alternating one loop iteration with `advance(period)` runs hours of robot
+// time in milliseconds and gives the same trace on every run.
+#[derive(Clone)]
+pub struct SimulatedClock {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        let start = Instant::now();
+        SimulatedClock {
+            state: Arc::new(Mutex::new(SimulatedState { start, now: start, sleepers: HashMap::new(), next_sleep_id: 1 })),
+        }
+    }
+
+    // Virtual time since the clock was created; use this rather than raw
+    // Instants when comparing traces between runs
+    pub fn elapsed(&self) -> Duration {
+        let state = lock_or_recover(&self.state, LOG_TARGET, "elapsed");
+        state.now - state.start
+    }
+
+    // Moves time forward and wakes every sleep that is now due
+    pub fn advance(&self, duration: Duration) {
+        let mut state = lock_or_recover(&self.state, LOG_TARGET, "advance");
+        state.now += duration;
+        let now = state.now;
+        let mut due = Vec::new();
+        state.sleepers.retain(|_, (deadline, waker)| {
+            if *deadline <= now {
+                due.push(waker.clone());
+                false
+            } else {
+                true
+            }
+        });
+        drop(state);
+        for waker in due {
+            waker.wake();
+        }
+    }
+
+    // Advances to `deadline` if it is in the future
+    #[allow(dead_code)]
+    pub fn advance_to(&self, deadline: Instant) {
+        let now = self.now();
+        if deadline > now {
+            self.advance(deadline - now);
+        }
+    }
+
+    // Earliest deadline any sleep is waiting for
+    #[allow(dead_code)]
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let state = lock_or_recover(&self.state, LOG_TARGET, "next_wakeup");
+        state.sleepers.values().map(|(deadline, _)| *deadline).min()
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        lock_or_recover(&self.state, LOG_TARGET, "now").now
+    }
+
+    // Counts from the Unix epoch, so timestamps are the same on every run
+    fn system_time(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + self.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let mut state = lock_or_recover(&self.state, LOG_TARGET, "sleep_until");
+        let id = state.next_sleep_id;
+        state.next_sleep_id += 1;
+        Box::pin(SimulatedSleep { clock: self.clone(), id, deadline })
+    }
+
+    // An idle system would otherwise wait forever for virtual time to pass,
+    // so idling moves the clock itself and lets other tasks run
+    fn idle(&self, duration: Duration) -> Sleep {
+        self.advance(duration);
+        Box::pin(tokio::task::yield_now())
+    }
+}
+
+struct SimulatedSleep {
+    clock: SimulatedClock,
+    id: u64,
+    deadline: Instant,
+}
+
+impl Future for SimulatedSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock_or_recover(&self.clock.state, LOG_TARGET, "sleep");
+        if state.now >= self.deadline {
+            state.sleepers.remove(&self.id);
+            return Poll::Ready(());
+        }
+        match state.sleepers.get_mut(&self.id) {
+            Some((_, waker)) if waker.will_wake(cx.waker()) => {},
+            Some((_, waker)) => *waker = cx.waker().clone(),
+            None => {
+                state.sleepers.insert(self.id, (self.deadline, cx.waker().clone()));
+            },
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for SimulatedSleep {
+    fn drop(&mut self) {
+        lock_or_recover(&self.clock.state, LOG_TARGET, "sleep").sleepers.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Scheduler;
+
+    #[tokio::test]
+    async fn test_simulated_sleep() {
+        let clock = SimulatedClock::new();
+        let sleeper = tokio::spawn(clock.sleep(Duration::from_secs(3600)));
+        tokio::task::yield_now().await;
+        assert_eq!(clock.next_wakeup(), Some(clock.now() + Duration::from_secs(3600)));
+
+        clock.advance(Duration::from_secs(1800));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+        clock.advance(Duration::from_secs(1800));
+        sleeper.await.unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+        assert_eq!(clock.system_time(), SystemTime::UNIX_EPOCH + Duration::from_secs(3600));
+
+        // A sleep keeps one registration however often it is polled, and
+        // gives it up when dropped
+        let mut sleep = clock.sleep(Duration::from_secs(1));
+        let waker = std::task::Waker::noop();
+        for _ in 0..3 {
+            assert!(sleep.as_mut().poll(&mut Context::from_waker(waker)).is_pending());
+        }
+        assert_eq!(lock_or_recover(&clock.state, LOG_TARGET, "test").sleepers.len(), 1);
+        drop(sleep);
+        assert_eq!(clock.next_wakeup(), None);
+    }
+
+    fn trace() -> Vec<(Duration, u32)> {
+        let clock = SimulatedClock::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(Arc::new(clock.clone()));
+        let motor = scheduler.add_periodic_task(3, Duration::from_millis(10), Duration::ZERO, Duration::from_millis(10)).unwrap();
+        let telemetry = scheduler.add_periodic_task(1, Duration::from_secs(1), Duration::ZERO, Duration::from_secs(1)).unwrap();
+
+        let mut trace = Vec::new();
+        // Ten simulated minutes
+        for _ in 0..60_000 {
+            while let Some(task) = scheduler.get_next_task() {
+                trace.push((clock.elapsed(), task.recurring_id.unwrap()));
+                scheduler.complete_task(task.id).unwrap();
+            }
+            clock.advance(Duration::from_millis(10));
+        }
+        assert_eq!(scheduler.recurring_stats(motor).unwrap().completed, 60_000);
+        assert_eq!(scheduler.recurring_stats(telemetry).unwrap().deadline_misses, 0);
+        trace
+    }
+
+    #[test]
+    fn test_deterministic_trace() {
+        assert_eq!(trace(), trace());
+    }
+}
@@ -0,0 +1,59 @@
+use std::error::Error;
+use std::fmt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecError {
+    pub codec: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} codec: {}", self.codec, self.message)
+    }
+}
+
+impl Error for CodecError {}
+
+// Turns typed message payloads into bytes and back. `NAME` is stored in the
+// message header so a receiver can tell which codec produced a payload.
+#[allow(dead_code)]
+pub trait Codec {
+    const NAME: &'static str;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError>;
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+// Human-readable, for debugging and interop with tools
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    const NAME: &'static str = "json";
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(value).map_err(|e| CodecError { codec: Self::NAME, message: e.to_string() })
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        serde_json::from_slice(bytes).map_err(|e| CodecError { codec: Self::NAME, message: e.to_string() })
+    }
+}
+
+// Compact binary encoding, for point clouds, images and other bulk data
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    const NAME: &'static str = "bincode";
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+        bincode::serialize(value).map_err(|e| CodecError { codec: Self::NAME, message: e.to_string() })
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        bincode::deserialize(bytes).map_err(|e| CodecError { codec: Self::NAME, message: e.to_string() })
+    }
+}
@@ -1,34 +1,219 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::fmt;
 use std::error::Error;
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use crate::clock::{self, SharedClock};
+use crate::ipc::IPC;
+use crate::topics::TopicRegistry;
+use crate::logging;
+use crate::memory_manager::{MemoryError, MemoryManager};
+use crate::multicore::{CpuSet, MultiCoreError, MAX_CORES};
+use crate::scheduler::{Candidate, Priority, RoundRobin, SchedulingPolicy};
+use crate::sync::{self, lock_or_recover};
+
+const LOG_TARGET: &str = "core_system";
+
+pub const DEFAULT_PRIORITY: u8 = 1;
+pub const DEFAULT_TIME_SLICE: Duration = Duration::from_millis(10);
+// Slices also end after this many polls, so they are deterministic under a simulated clock
+pub const MAX_POLLS_PER_SLICE: u32 = 64;
+// Exit code given to processes that are still alive when the system shuts down
+pub const SHUTDOWN_EXIT_CODE: i32 = -1;
+// Exit code of the old instance when a process is restarted
+pub const RESTART_EXIT_CODE: i32 = -2;
+// Exit code of a process whose body panicked
+pub const PANIC_EXIT_CODE: i32 = -3;
+// Exit codes are kept for this many terminated processes, oldest dropped first
+pub const MAX_EXIT_CODES: usize = 1024;
+
+// The code a process executes. The output is the process's exit code.
+pub type ProcessBody = Pin<Box<dyn Future<Output = i32> + Send>>;
+// Builds a fresh body each time a restartable process is (re)started
+pub type ProcessFactory = Arc<dyn Fn() -> ProcessBody + Send + Sync>;
 
 // Custom error type for CoreSystem
 #[derive(Debug)]
 pub enum CoreSystemError {
-    LockError,
-    ProcessCreationError,
-    SchedulingError,
+    ProcessCreationError { name: String, source: Box<CoreSystemError> },
+    SchedulingError { pid: Option<u32>, source: Box<CoreSystemError> },
+    ProcessNotFound { pid: u32, operation: &'static str },
+    InvalidStateTransition { pid: u32, state: ProcessState, operation: &'static str },
+    Memory { pid: u32, source: MemoryError },
+    ProcessPanicked { pid: u32, message: String },
+    // The process was not started with `spawn_restartable`, so there is no body to run again
+    NotRestartable { pid: u32 },
+}
+
+impl CoreSystemError {
+    // The process the error is attributed to, if any
+    pub fn pid(&self) -> Option<u32> {
+        match self {
+            CoreSystemError::ProcessCreationError { source, .. } => source.pid(),
+            CoreSystemError::SchedulingError { pid, source } => pid.or_else(|| source.pid()),
+            CoreSystemError::ProcessNotFound { pid, .. }
+            | CoreSystemError::InvalidStateTransition { pid, .. }
+            | CoreSystemError::Memory { pid, .. }
+            | CoreSystemError::ProcessPanicked { pid, .. }
+            | CoreSystemError::NotRestartable { pid } => Some(*pid),
+        }
+    }
 }
 
 impl fmt::Display for CoreSystemError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            CoreSystemError::LockError => write!(f, "Failed to acquire lock"),
-            CoreSystemError::ProcessCreationError => write!(f, "Failed to create process"),
-            CoreSystemError::SchedulingError => write!(f, "Error during scheduling"),
+            CoreSystemError::ProcessCreationError { name, source } => {
+                write!(f, "Failed to create process {}: {}", name, source)
+            }
+            CoreSystemError::SchedulingError { pid: Some(pid), source } => {
+                write!(f, "Error during scheduling of PID {}: {}", pid, source)
+            }
+            CoreSystemError::SchedulingError { pid: None, source } => write!(f, "Error during scheduling: {}", source),
+            CoreSystemError::ProcessNotFound { pid, operation } => {
+                write!(f, "No process with PID {} for {}", pid, operation)
+            }
+            CoreSystemError::InvalidStateTransition { pid, state, operation } => {
+                write!(f, "Cannot {} PID {} in state {:?}", operation, pid, state)
+            }
+            CoreSystemError::Memory { pid, source } => write!(f, "Memory error for PID {}: {}", pid, source),
+            CoreSystemError::ProcessPanicked { pid, message } => write!(f, "PID {} panicked: {}", pid, message),
+            CoreSystemError::NotRestartable { pid } => write!(f, "PID {} was not spawned restartable", pid),
+        }
+    }
+}
+
+impl Error for CoreSystemError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CoreSystemError::ProcessCreationError { source, .. } => Some(source.as_ref()),
+            CoreSystemError::SchedulingError { source, .. } => Some(source.as_ref()),
+            CoreSystemError::Memory { source, .. } => Some(source),
+            _ => None,
         }
     }
 }
 
-impl Error for CoreSystemError {}
+// How `run_until` reacts when a scheduling quantum fails. Each variant gives
+// up and escalates after `max_attempts` consecutive failures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecoveryPolicy {
+    // Try the next quantum straight away
+    #[allow(dead_code)]
+    Retry { max_attempts: u32 },
+    // Wait `initial` after the first failure, doubling up to `max`
+    Backoff { initial: Duration, max: Duration, max_attempts: u32 },
+    // Restart the process the error is attributed to, or retry if there is none
+    #[allow(dead_code)]
+    RestartProcess { max_attempts: u32 },
+    // Stop running and hand the error to the caller of run_until
+    #[allow(dead_code)]
+    Escalate,
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecoveryAction {
+    Retry(Duration),
+    Restart(u32),
+    Escalate,
+}
+
+impl RecoveryPolicy {
+    // `attempt` counts consecutive failures, starting at 1
+    pub fn next_action(&self, attempt: u32, pid: Option<u32>) -> RecoveryAction {
+        match *self {
+            RecoveryPolicy::Retry { max_attempts } if attempt <= max_attempts => RecoveryAction::Retry(Duration::ZERO),
+            RecoveryPolicy::Backoff { initial, max, max_attempts } if attempt <= max_attempts => {
+                let factor = 2u32.saturating_pow(attempt - 1);
+                RecoveryAction::Retry(initial.saturating_mul(factor).min(max))
+            },
+            RecoveryPolicy::RestartProcess { max_attempts } if attempt <= max_attempts => match pid {
+                Some(pid) => RecoveryAction::Restart(pid),
+                None => RecoveryAction::Retry(Duration::ZERO),
+            },
+            _ => RecoveryAction::Escalate,
+        }
+    }
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        RecoveryPolicy::Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            max_attempts: 10,
+        }
+    }
+}
+
+// Process control block
 pub struct Process {
     id: u32,
+    name: String,
+    parent: Option<u32>,
+    children: Vec<u32>,
     state: ProcessState,
+    priority: u8,
+    deadline: Option<Instant>,
+    affinity: CpuSet,
+    created_at: Instant,
+    cpu_slices: u64,
+    blocked_on: Option<String>,
+    exit_code: Option<i32>,
+    memory: Vec<usize>,
+    body: Option<ProcessBody>,
+    factory: Option<ProcessFactory>,
+    // Set by the process's waker; a parked process becomes ready again once it is set
+    woken: Arc<AtomicBool>,
+    parked: bool,
+}
+
+impl Process {
+    fn info(&self) -> ProcessInfo {
+        ProcessInfo {
+            pid: self.id,
+            name: self.name.clone(),
+            parent: self.parent,
+            children: self.children.clone(),
+            state: self.state,
+            priority: self.priority,
+            deadline: self.deadline,
+            affinity: self.affinity,
+            created_at: self.created_at,
+            cpu_slices: self.cpu_slices,
+            blocked_on: self.blocked_on.clone(),
+            mailbox: self.id,
+            memory: self.memory.clone(),
+        }
+    }
+}
+
+// Point-in-time copy of a process control block, as returned by `process_table`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub parent: Option<u32>,
+    pub children: Vec<u32>,
+    pub state: ProcessState,
+    pub priority: u8,
+    pub deadline: Option<Instant>,
+    pub affinity: CpuSet,
+    pub created_at: Instant,
+    pub cpu_slices: u64,
+    pub blocked_on: Option<String>,
+    pub mailbox: u32,
+    pub memory: Vec<usize>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProcessState {
     Ready,
     Running,
@@ -36,104 +221,737 @@ pub enum ProcessState {
     Terminated,
 }
 
+enum SliceOutcome {
+    Exited(i32),
+    Parked,
+    Preempted,
+    Panicked(String),
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+struct ProcessWaker {
+    woken: Arc<AtomicBool>,
+}
+
+impl Wake for ProcessWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+}
+
+// Cooperative yield point for process bodies. The process stays runnable and
+// may be preempted here if its time slice has expired.
+#[allow(dead_code)]
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// Final state of a process as reported by `CoreSystem::shutdown`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessExit {
+    pub pid: u32,
+    pub name: String,
+    pub state_at_shutdown: ProcessState,
+    pub exit_code: i32,
+}
+
+// Resolves with the exit code of a process once it has been terminated
+pub struct WaitHandle {
+    pid: u32,
+    receiver: oneshot::Receiver<i32>,
+}
+
+impl Future for WaitHandle {
+    type Output = Result<i32, CoreSystemError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pid = self.pid;
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| result.map_err(|_| CoreSystemError::ProcessNotFound { pid, operation: "wait" }))
+    }
+}
+
 pub struct CoreSystem {
     processes: Arc<Mutex<VecDeque<Process>>>,
     current_process: Option<Process>,
     next_pid: u32,
+    exit_codes: HashMap<u32, i32>,
+    // PIDs in `exit_codes`, in termination order
+    exited: VecDeque<u32>,
+    waiters: HashMap<u32, Vec<oneshot::Sender<i32>>>,
+    ipc: IPC,
+    topics: TopicRegistry,
+    memory_manager: Arc<Mutex<MemoryManager>>,
+    time_slice: Duration,
+    // Logical core this system runs its processes on, checked against their affinity
+    core: usize,
+    policy: Box<dyn SchedulingPolicy>,
+    recovery_policy: RecoveryPolicy,
+    clock: SharedClock,
 }
 
 impl CoreSystem {
+    #[allow(dead_code)]
     pub fn new() -> Self {
-        log("Creating new CoreSystem");
+        Self::with_resources(IPC::new(), Arc::new(Mutex::new(MemoryManager::new(1024 * 1024))))
+    }
+
+    // Shares the IPC mailboxes and memory pool with the rest of the system so
+    // that a process's mailbox and allocations can be released when it dies
+    pub fn with_resources(ipc: IPC, memory_manager: Arc<Mutex<MemoryManager>>) -> Self {
+        logging::info(LOG_TARGET, "Creating new CoreSystem");
         CoreSystem {
             processes: Arc::new(Mutex::new(VecDeque::new())),
             current_process: None,
             next_pid: 1,
+            exit_codes: HashMap::new(),
+            exited: VecDeque::new(),
+            waiters: HashMap::new(),
+            topics: TopicRegistry::new(ipc.clone()),
+            ipc,
+            memory_manager,
+            time_slice: DEFAULT_TIME_SLICE,
+            core: 0,
+            policy: Box::new(RoundRobin),
+            recovery_policy: RecoveryPolicy::default(),
+            clock: clock::system_clock(),
+        }
+    }
+
+    // Shares the topic registry, so a process's publications and subscriptions
+    // are dropped when it dies
+    pub fn set_topics(&mut self, topics: TopicRegistry) {
+        self.topics = topics;
+    }
+
+    // Replaces the time source used for deadlines, time slices and idle sleeps
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    #[allow(dead_code)]
+    pub fn clock(&self) -> SharedClock {
+        Arc::clone(&self.clock)
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_recovery_policy(&mut self, recovery_policy: RecoveryPolicy) {
+        self.recovery_policy = recovery_policy;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_scheduling_policy(&mut self, policy: Box<dyn SchedulingPolicy>) {
+        logging::info(LOG_TARGET, &format!("Switching scheduling policy to {}", policy.name()));
+        self.policy = policy;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_time_slice(&mut self, time_slice: Duration) {
+        self.time_slice = time_slice;
+    }
+
+    // Processes whose affinity excludes `core` stay queued until it is changed
+    #[allow(dead_code)]
+    pub fn set_core(&mut self, core: usize) -> Result<(), MultiCoreError> {
+        if core >= MAX_CORES {
+            return Err(MultiCoreError::InvalidCore(core));
         }
+        self.core = core;
+        Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn create_process(&mut self) -> Result<u32, CoreSystemError> {
+        let name = format!("process-{}", self.next_pid);
+        self.create_process_with(&name, None, DEFAULT_PRIORITY)
+    }
+
+    pub fn create_process_with(&mut self, name: &str, parent: Option<u32>, priority: u8) -> Result<u32, CoreSystemError> {
+        self.insert_process(name, parent, priority, None, None)
+    }
+
+    // Creates a process that executes `body`; it terminates with the body's output as exit code
+    pub fn spawn<F>(&mut self, name: &str, parent: Option<u32>, priority: u8, body: F) -> Result<u32, CoreSystemError>
+    where
+        F: Future<Output = i32> + Send + 'static,
+    {
+        self.insert_process(name, parent, priority, Some(Box::pin(body)), None)
+    }
+
+    // Like `spawn`, but keeps `factory` so that `restart_process` can start it again
+    #[allow(dead_code)]
+    pub fn spawn_restartable<F, Fut>(&mut self, name: &str, parent: Option<u32>, priority: u8, factory: F) -> Result<u32, CoreSystemError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = i32> + Send + 'static,
+    {
+        let factory: ProcessFactory = Arc::new(move || Box::pin(factory()) as ProcessBody);
+        self.insert_process(name, parent, priority, Some(factory()), Some(factory))
+    }
+
+    // Terminates `pid` if it is still alive and starts a new process with the
+    // same name, parent and priority. Returns the PID of the new process.
+    // Only processes created with `spawn_restartable` can be restarted; others
+    // are left alone and fail with NotRestartable.
+    pub fn restart_process(&mut self, pid: u32) -> Result<u32, CoreSystemError> {
+        let (name, parent, priority, affinity, factory) = self.update_process(pid, "restart", |process| {
+            match process.factory.clone() {
+                Some(factory) => Ok((process.name.clone(), process.parent, process.priority, process.affinity, factory)),
+                None => Err(CoreSystemError::NotRestartable { pid }),
+            }
+        })?;
+        if self.process_state(pid) != Some(ProcessState::Terminated) {
+            self.terminate(pid, RESTART_EXIT_CODE)?;
+        }
+        let body = factory();
+        let new_pid = self.insert_process(&name, parent, priority, Some(body), Some(factory))?;
+        self.set_affinity(new_pid, affinity)?;
+        logging::warn(LOG_TARGET, &format!("Restarted process {} (PID {} -> {})", name, pid, new_pid));
+        Ok(new_pid)
+    }
+
+    fn insert_process(
+        &mut self,
+        name: &str,
+        parent: Option<u32>,
+        priority: u8,
+        body: Option<ProcessBody>,
+        factory: Option<ProcessFactory>,
+    ) -> Result<u32, CoreSystemError> {
         let pid = self.next_pid;
+        if let Some(parent_pid) = parent {
+            let adopted = self.update_process(parent_pid, "adopt child", |parent| match parent.state {
+                ProcessState::Terminated => Err(CoreSystemError::InvalidStateTransition {
+                    pid: parent_pid,
+                    state: parent.state,
+                    operation: "adopt child",
+                }),
+                _ => {
+                    parent.children.push(pid);
+                    Ok(())
+                },
+            });
+            if let Err(e) = adopted {
+                logging::error(LOG_TARGET, &format!("Cannot create child of PID {}: {}", parent_pid, e));
+                return Err(CoreSystemError::ProcessCreationError { name: name.to_string(), source: Box::new(e) });
+            }
+        }
+
         self.next_pid += 1;
         let process = Process {
             id: pid,
+            name: name.to_string(),
+            parent,
+            children: Vec::new(),
             state: ProcessState::Ready,
+            priority,
+            deadline: None,
+            affinity: CpuSet::all(),
+            created_at: self.clock.now(),
+            cpu_slices: 0,
+            blocked_on: None,
+            exit_code: None,
+            memory: Vec::new(),
+            body,
+            factory,
+            woken: Arc::new(AtomicBool::new(false)),
+            parked: false,
         };
-        
-        match self.processes.lock() {
-            Ok(mut processes) => {
-                processes.push_back(process);
-                log(&format!("Created new process with PID: {}", pid));
-                Ok(pid)
+
+        lock_or_recover(&self.processes, LOG_TARGET, "create_process").push_back(process);
+        self.ipc.create_mailbox(pid);
+        logging::info(LOG_TARGET, &format!("Created new process {} with PID: {}", name, pid));
+        Ok(pid)
+    }
+
+    pub fn process_table(&self) -> Result<Vec<ProcessInfo>, CoreSystemError> {
+        let processes = lock_or_recover(&self.processes, LOG_TARGET, "process_table");
+        let mut table: Vec<ProcessInfo> = self.current_process
+            .iter()
+            .chain(processes.iter())
+            .map(Process::info)
+            .collect();
+        table.sort_by_key(|info| info.pid);
+        Ok(table)
+    }
+
+    #[allow(dead_code)]
+    pub fn allocate_memory(&mut self, pid: u32, size: usize) -> Result<usize, CoreSystemError> {
+        let memory_manager = Arc::clone(&self.memory_manager);
+        self.update_process(pid, "allocate memory", |process| {
+            if process.state == ProcessState::Terminated {
+                return Err(CoreSystemError::InvalidStateTransition {
+                    pid,
+                    state: process.state,
+                    operation: "allocate memory",
+                });
+            }
+            let address = lock_or_recover(&memory_manager, LOG_TARGET, "allocate_memory")
+                .allocate(size)
+                .map_err(|source| CoreSystemError::Memory { pid, source })?;
+            process.memory.push(address);
+            Ok(address)
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn free_memory(&mut self, pid: u32, address: usize) -> Result<(), CoreSystemError> {
+        let memory_manager = Arc::clone(&self.memory_manager);
+        self.update_process(pid, "free memory", |process| {
+            let index = process.memory.iter().position(|&a| a == address)
+                .ok_or(CoreSystemError::Memory { pid, source: MemoryError::InvalidAddress(address) })?;
+            lock_or_recover(&memory_manager, LOG_TARGET, "free_memory")
+                .deallocate(address)
+                .map_err(|source| CoreSystemError::Memory { pid, source })?;
+            process.memory.swap_remove(index);
+            Ok(())
+        })
+    }
+
+    // Exit code of a terminated process, whether or not it has been reaped
+    // yet, as long as it is among the last MAX_EXIT_CODES to terminate
+    pub fn exit_code(&self, pid: u32) -> Option<i32> {
+        self.exit_codes.get(&pid).copied()
+    }
+
+    pub fn process_state(&self, pid: u32) -> Option<ProcessState> {
+        if let Some(current) = self.current_process.as_ref().filter(|p| p.id == pid) {
+            return Some(current.state);
+        }
+        if self.exit_codes.contains_key(&pid) {
+            return Some(ProcessState::Terminated);
+        }
+        lock_or_recover(&self.processes, LOG_TARGET, "process_state")
+            .iter()
+            .find(|p| p.id == pid)
+            .map(|p| p.state)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_priority(&mut self, pid: u32, priority: u8) -> Result<(), CoreSystemError> {
+        self.update_process(pid, "set priority", |process| {
+            process.priority = priority;
+            Ok(())
+        })
+    }
+
+    // Cores the process may run on; see `set_core`
+    pub fn set_affinity(&mut self, pid: u32, affinity: CpuSet) -> Result<(), CoreSystemError> {
+        self.update_process(pid, "set affinity", |process| {
+            process.affinity = affinity;
+            Ok(())
+        })
+    }
+
+    // The deadline is relative to now, like Scheduler::add_task; used by EDF
+    #[allow(dead_code)]
+    pub fn set_deadline(&mut self, pid: u32, deadline: Duration) -> Result<(), CoreSystemError> {
+        let deadline = self.clock.now() + deadline;
+        self.update_process(pid, "set deadline", |process| {
+            process.deadline = Some(deadline);
+            Ok(())
+        })
+    }
+
+    pub fn block(&mut self, pid: u32, reason: &str) -> Result<(), CoreSystemError> {
+        self.update_process(pid, "block", |process| match process.state {
+            ProcessState::Ready | ProcessState::Running => {
+                process.state = ProcessState::Blocked;
+                process.blocked_on = Some(reason.to_string());
+                Ok(())
             },
-            Err(_) => {
-                log_error("Failed to acquire lock while creating process");
-                Err(CoreSystemError::LockError)
+            state => Err(CoreSystemError::InvalidStateTransition { pid, state, operation: "block" }),
+        })?;
+        logging::debug(LOG_TARGET, &format!("Blocked process with PID: {} ({})", pid, reason));
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn unblock(&mut self, pid: u32) -> Result<(), CoreSystemError> {
+        self.update_process(pid, "unblock", |process| match process.state {
+            ProcessState::Blocked => {
+                process.state = ProcessState::Ready;
+                process.blocked_on = None;
+                process.parked = false;
+                Ok(())
+            },
+            state => Err(CoreSystemError::InvalidStateTransition { pid, state, operation: "unblock" }),
+        })?;
+        logging::debug(LOG_TARGET, &format!("Unblocked process with PID: {}", pid));
+        Ok(())
+    }
+
+    pub fn terminate(&mut self, pid: u32, exit_code: i32) -> Result<(), CoreSystemError> {
+        let (memory, parent, children) = self.update_process(pid, "terminate", |process| match process.state {
+            ProcessState::Terminated => Err(CoreSystemError::InvalidStateTransition {
+                pid,
+                state: process.state,
+                operation: "terminate",
+            }),
+            _ => {
+                process.state = ProcessState::Terminated;
+                process.blocked_on = None;
+                process.exit_code = Some(exit_code);
+                process.body = None;
+                Ok((std::mem::take(&mut process.memory), process.parent, process.children.clone()))
+            },
+        })?;
+
+        self.release_resources(pid, memory);
+        if let Some(parent_pid) = parent {
+            // The parent may already be gone; there is nothing to detach from then
+            let _ = self.update_process(parent_pid, "detach child", |parent| {
+                parent.children.retain(|&child| child != pid);
+                Ok(())
+            });
+        }
+        for child in children {
+            let _ = self.update_process(child, "orphan", |child| {
+                child.parent = None;
+                Ok(())
+            });
+        }
+
+        self.record_exit(pid, exit_code);
+        for waiter in self.waiters.remove(&pid).unwrap_or_default() {
+            let _ = waiter.send(exit_code);
+        }
+        logging::info(LOG_TARGET, &format!("Terminated process with PID: {} (exit code {})", pid, exit_code));
+        Ok(())
+    }
+
+    fn record_exit(&mut self, pid: u32, exit_code: i32) {
+        if self.exited.len() == MAX_EXIT_CODES {
+            if let Some(oldest) = self.exited.pop_front() {
+                self.exit_codes.remove(&oldest);
             }
         }
+        self.exit_codes.insert(pid, exit_code);
+        self.exited.push_back(pid);
+    }
+
+    // The returned handle does not borrow the CoreSystem, so the process can
+    // be terminated while a caller is awaiting it.
+    #[allow(dead_code)]
+    pub fn wait(&mut self, pid: u32) -> WaitHandle {
+        let (sender, receiver) = oneshot::channel();
+        if let Some(&exit_code) = self.exit_codes.get(&pid) {
+            let _ = sender.send(exit_code);
+        } else if self.process_state(pid).is_some() {
+            self.waiters.entry(pid).or_default().push(sender);
+        }
+        WaitHandle { pid, receiver }
     }
 
     pub fn schedule(&mut self) -> Result<(), CoreSystemError> {
         if let Some(mut current) = self.current_process.take() {
             current.state = ProcessState::Ready;
-            match self.processes.lock() {
-                Ok(mut processes) => processes.push_back(current),
-                Err(_) => {
-                    log_error("Failed to acquire lock while scheduling (push_back)");
-                    return Err(CoreSystemError::LockError);
-                }
+            lock_or_recover(&self.processes, LOG_TARGET, "schedule").push_back(current);
+        }
+
+        let mut processes = lock_or_recover(&self.processes, LOG_TARGET, "schedule");
+        processes.retain(|process| {
+            if process.state == ProcessState::Terminated {
+                logging::debug(LOG_TARGET, &format!(
+                    "Reaped process with PID: {} (exit code {})",
+                    process.id,
+                    process.exit_code.unwrap_or_default()
+                ));
+                false
+            } else {
+                true
+            }
+        });
+
+        // Processes parked on a pending future become ready once they have been woken
+        for process in processes.iter_mut() {
+            if process.parked && process.woken.load(Ordering::SeqCst) {
+                process.parked = false;
+                process.state = ProcessState::Ready;
+                process.blocked_on = None;
             }
         }
-        
-        match self.processes.lock() {
-            Ok(mut processes) => {
-                if let Some(next) = processes.pop_front() {
-                    next.state = ProcessState::Running;
-                    self.current_process = Some(next);
-                    log(&format!("Scheduled process with PID: {}", self.current_process.as_ref().unwrap().id));
-                    Ok(())
-                } else {
-                    log("No processes to schedule");
-                    Ok(())
-                }
+
+        // Blocked processes, and those pinned to other cores, keep their place
+        // in the queue until they are unblocked
+        let core = self.core;
+        let (indices, candidates): (Vec<usize>, Vec<Candidate>) = processes
+            .iter()
+            .enumerate()
+            .filter(|(_, process)| process.state == ProcessState::Ready && process.affinity.contains(core))
+            .map(|(index, process)| {
+                (index, Candidate {
+                    id: process.id,
+                    priority: Priority(process.priority),
+                    deadline: process.deadline,
+                })
+            })
+            .unzip();
+        let selected = self.policy.select(&candidates).and_then(|i| indices.get(i).copied());
+
+        match selected {
+            Some(index) => {
+                let mut next = processes.remove(index).unwrap();
+                next.state = ProcessState::Running;
+                next.cpu_slices += 1;
+                logging::debug(LOG_TARGET, &format!("Scheduled process with PID: {}", next.id));
+                self.current_process = Some(next);
             },
-            Err(_) => {
-                log_error("Failed to acquire lock while scheduling (pop_front)");
-                Err(CoreSystemError::LockError)
-            }
+            None if processes.is_empty() => logging::debug(LOG_TARGET, "No processes to schedule"),
+            None => logging::debug(LOG_TARGET, "All processes are blocked"),
         }
+        Ok(())
+    }
+
+    // Performs one scheduling quantum: picks the next process and runs it for
+    // up to one time slice. Returns whether any process body was executed.
+    pub fn step(&mut self) -> Result<bool, CoreSystemError> {
+        self.schedule().map_err(|e| CoreSystemError::SchedulingError { pid: None, source: Box::new(e) })?;
+        let pid = self.current_process.as_ref().map(|p| p.id);
+        self.execute_current().map_err(|e| CoreSystemError::SchedulingError { pid, source: Box::new(e) })
+    }
+
+    #[allow(dead_code)]
+    pub async fn run(&mut self) -> Result<Vec<ProcessExit>, CoreSystemError> {
+        self.run_until(std::future::pending()).await
     }
 
-    pub async fn run(&mut self) {
-        log("Core System running...");
+    // Runs until `shutdown` resolves, then terminates every remaining process.
+    // Failed quanta are handled by the recovery policy; an escalated error
+    // also shuts the system down and is returned to the caller.
+    pub async fn run_until<F: Future<Output = ()>>(&mut self, shutdown: F) -> Result<Vec<ProcessExit>, CoreSystemError> {
+        logging::info(LOG_TARGET, "Core System running...");
+        tokio::pin!(shutdown);
+        let mut failures = 0;
+        let mut delay = Duration::ZERO;
         loop {
-            match self.schedule() {
-                Ok(_) => {
-                    // Simulate process execution
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            let result = tokio::select! {
+                biased;
+                _ = &mut shutdown => break,
+                result = self.tick(delay) => result,
+            };
+            delay = Duration::ZERO;
+
+            let error = match result {
+                Ok(()) => {
+                    failures = 0;
+                    continue;
+                },
+                Err(e) => e,
+            };
+            failures += 1;
+            logging::error(LOG_TARGET, &format!("Error during scheduling (attempt {}): {}", failures, error));
+            match self.recovery_policy.next_action(failures, error.pid()) {
+                RecoveryAction::Retry(backoff) => delay = backoff,
+                RecoveryAction::Restart(pid) => {
+                    if let Err(e) = self.restart_process(pid) {
+                        logging::error(LOG_TARGET, &format!("Failed to restart PID {}, escalating: {}", pid, e));
+                        self.shutdown();
+                        return Err(e);
+                    }
                 },
-                Err(e) => {
-                    log_error(&format!("Error during scheduling: {}", e));
-                    // In a real system, you might want to implement some error recovery here
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                RecoveryAction::Escalate => {
+                    logging::error(LOG_TARGET, "Recovery failed, escalating");
+                    self.shutdown();
+                    return Err(error);
+                },
+            }
+        }
+        Ok(self.shutdown())
+    }
+
+    async fn tick(&mut self, delay: Duration) -> Result<(), CoreSystemError> {
+        if !delay.is_zero() {
+            self.clock.sleep(delay).await;
+        }
+        if self.step()? {
+            tokio::task::yield_now().await;
+        } else {
+            // Nothing runnable; give pending futures a time slice to make
+            // progress. A simulated clock is advanced by the slice instead.
+            self.clock.idle(self.time_slice).await;
+        }
+        Ok(())
+    }
+
+    pub fn shutdown(&mut self) -> Vec<ProcessExit> {
+        logging::info(LOG_TARGET, "Core System shutting down...");
+        let table = match self.process_table() {
+            Ok(table) => table,
+            Err(e) => {
+                logging::error(LOG_TARGET, &format!("Cannot read process table during shutdown: {}", e));
+                return Vec::new();
+            }
+        };
+
+        let mut report = Vec::with_capacity(table.len());
+        for info in table {
+            if info.state != ProcessState::Terminated {
+                if let Err(e) = self.terminate(info.pid, SHUTDOWN_EXIT_CODE) {
+                    logging::error(LOG_TARGET, &format!("Failed to terminate PID {} during shutdown: {}", info.pid, e));
                 }
             }
+            report.push(ProcessExit {
+                pid: info.pid,
+                name: info.name,
+                state_at_shutdown: info.state,
+                exit_code: self.exit_codes.get(&info.pid).copied().unwrap_or(SHUTDOWN_EXIT_CODE),
+            });
+        }
+
+        // Reap everything that was just terminated
+        if let Err(e) = self.schedule() {
+            logging::error(LOG_TARGET, &format!("Failed to reap processes during shutdown: {}", e));
+        }
+        for exit in &report {
+            logging::info(LOG_TARGET, &format!(
+                "PID {} ({}) was {:?} at shutdown, exit code {}",
+                exit.pid, exit.name, exit.state_at_shutdown, exit.exit_code
+            ));
         }
+        report
     }
-}
 
-// Simple logging functions
-fn log(message: &str) {
-    println!("[INFO] {}", message);
-}
+    // Polls the running process's body until it completes, parks on a pending
+    // future, or its time slice expires. Returns whether a body was polled.
+    fn execute_current(&mut self) -> Result<bool, CoreSystemError> {
+        let slice_end = self.clock.now() + self.time_slice;
+        let clock = Arc::clone(&self.clock);
+        let (pid, outcome) = match self.current_process.as_mut() {
+            Some(current) => match current.body.as_mut() {
+                Some(body) => {
+                    let previous_pid = logging::set_current_pid(Some(current.id));
+                    let waker = Waker::from(Arc::new(ProcessWaker { woken: Arc::clone(&current.woken) }));
+                    let mut cx = Context::from_waker(&waker);
+                    let mut polls = 0;
+                    let outcome = loop {
+                        polls += 1;
+                        current.woken.store(false, Ordering::SeqCst);
+                        // A panicking body must not unwind through the kernel
+                        let poll = panic::catch_unwind(AssertUnwindSafe(|| body.as_mut().poll(&mut cx)));
+                        match poll {
+                            Err(payload) => break SliceOutcome::Panicked(panic_message(payload.as_ref())),
+                            Ok(Poll::Ready(exit_code)) => break SliceOutcome::Exited(exit_code),
+                            Ok(Poll::Pending) if !current.woken.load(Ordering::SeqCst) => break SliceOutcome::Parked,
+                            // Preempted; schedule() puts it back at the end of the queue
+                            Ok(Poll::Pending) if polls >= MAX_POLLS_PER_SLICE || clock.now() >= slice_end => {
+                                break SliceOutcome::Preempted
+                            },
+                            Ok(Poll::Pending) => continue,
+                        }
+                    };
+                    logging::set_current_pid(previous_pid);
+                    (current.id, outcome)
+                },
+                None => return Ok(false),
+            },
+            None => return Ok(false),
+        };
+
+        match outcome {
+            SliceOutcome::Exited(exit_code) => self.terminate(pid, exit_code)?,
+            SliceOutcome::Parked => {
+                let reason = if self.ipc.has_waiters(pid) { "message" } else { "pending future" };
+                self.block(pid, reason)?;
+                self.update_process(pid, "park", |process| {
+                    process.parked = true;
+                    Ok(())
+                })?;
+            },
+            SliceOutcome::Preempted => {},
+            SliceOutcome::Panicked(message) => {
+                sync::record_fault(LOG_TARGET, &format!("PID {} panicked: {}", pid, message));
+                self.terminate(pid, PANIC_EXIT_CODE)?;
+                return Err(CoreSystemError::ProcessPanicked { pid, message });
+            },
+        }
+        Ok(true)
+    }
+
+    fn release_resources(&mut self, pid: u32, memory: Vec<usize>) {
+        let mut memory_manager = lock_or_recover(&self.memory_manager, LOG_TARGET, "release_resources");
+        for address in &memory {
+            if let Err(e) = memory_manager.deallocate(*address) {
+                logging::error(LOG_TARGET, &format!("Failed to free memory of PID {}: {}", pid, e));
+            }
+        }
+        drop(memory_manager);
+        self.topics.remove_process(pid);
+        let dropped = self.ipc.remove_mailbox(pid).unwrap_or_else(|e| {
+            logging::warn(LOG_TARGET, &e.to_string());
+            0
+        });
+        logging::info(LOG_TARGET, &format!(
+            "Released {} allocations and mailbox ({} undelivered messages) of PID: {}",
+            memory.len(),
+            dropped,
+            pid
+        ));
+    }
+
+    // Applies a change to a process wherever it lives. A running process
+    // that leaves the Running state gives up the CPU and goes back to the queue.
+    fn update_process<T, F>(&mut self, pid: u32, operation: &'static str, change: F) -> Result<T, CoreSystemError>
+    where
+        F: FnOnce(&mut Process) -> Result<T, CoreSystemError>,
+    {
+        if self.current_process.as_ref().map(|p| p.id) == Some(pid) {
+            let mut current = self.current_process.take().unwrap();
+            let result = change(&mut current);
+            if current.state == ProcessState::Running {
+                self.current_process = Some(current);
+                return result;
+            }
+            lock_or_recover(&self.processes, LOG_TARGET, operation).push_back(current);
+            return result;
+        }
 
-fn log_error(message: &str) {
-    eprintln!("[ERROR] {}", message);
+        match lock_or_recover(&self.processes, LOG_TARGET, operation).iter_mut().find(|p| p.id == pid) {
+            Some(process) => change(process),
+            None => Err(CoreSystemError::ProcessNotFound { pid, operation }),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::AtomicUsize;
 
     #[test]
     fn test_create_process() {
@@ -141,7 +959,7 @@ mod tests {
         let result = core_system.create_process();
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 1);
-        
+
         let result = core_system.create_process();
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 2);
@@ -150,7 +968,7 @@ mod tests {
     #[test]
     fn test_schedule() {
         let mut core_system = CoreSystem::new();
-        
+
         // Create two processes
         let _ = core_system.create_process();
         let _ = core_system.create_process();
@@ -173,10 +991,371 @@ mod tests {
     #[test]
     fn test_schedule_empty() {
         let mut core_system = CoreSystem::new();
-        
+
         // Scheduling with no processes should not error
         let result = core_system.schedule();
         assert!(result.is_ok());
         assert!(core_system.current_process.is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_schedule_skips_blocked() {
+        let mut core_system = CoreSystem::new();
+        let pid1 = core_system.create_process().unwrap();
+        let pid2 = core_system.create_process().unwrap();
+
+        core_system.block(pid1, "waiting for sensor").unwrap();
+        assert_eq!(core_system.process_state(pid1), Some(ProcessState::Blocked));
+
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, pid2);
+
+        // Blocking the running process gives up the CPU
+        core_system.block(pid2, "waiting for message").unwrap();
+        assert!(core_system.current_process.is_none());
+        core_system.schedule().unwrap();
+        assert!(core_system.current_process.is_none());
+
+        core_system.unblock(pid1).unwrap();
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, pid1);
+        assert!(core_system.unblock(pid1).is_err());
+
+        // A process pinned to another core is skipped like a blocked one
+        core_system.set_affinity(pid1, CpuSet::single(3).unwrap()).unwrap();
+        core_system.schedule().unwrap();
+        assert!(core_system.current_process.is_none());
+        core_system.set_core(3).unwrap();
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, pid1);
+        assert_eq!(core_system.set_core(64), Err(MultiCoreError::InvalidCore(64)));
+    }
+
+    #[tokio::test]
+    async fn test_terminate_and_wait() {
+        let mut core_system = CoreSystem::new();
+        let pid1 = core_system.create_process().unwrap();
+        let pid2 = core_system.create_process().unwrap();
+
+        let waiter = core_system.wait(pid1);
+        core_system.schedule().unwrap();
+        core_system.terminate(pid1, 3).unwrap();
+        assert_eq!(waiter.await.unwrap(), 3);
+        assert!(core_system.terminate(pid1, 0).is_err());
+
+        // Terminated processes are reaped on the next schedule
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, pid2);
+        assert_eq!(core_system.processes.lock().unwrap().len(), 0);
+
+        // Waiting on an already reaped process still reports its exit code
+        assert_eq!(core_system.wait(pid1).await.unwrap(), 3);
+        assert!(matches!(core_system.wait(42).await, Err(CoreSystemError::ProcessNotFound { pid: 42, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_execute_process_bodies() {
+        let mut core_system = CoreSystem::new();
+        core_system.set_time_slice(Duration::from_millis(1));
+        let ticks = Arc::new(AtomicUsize::new(0));
+
+        // A busy process is preempted at slice expiry and the other process gets the CPU
+        let busy_ticks = Arc::clone(&ticks);
+        let busy = core_system.spawn("busy", None, DEFAULT_PRIORITY, async move {
+            loop {
+                busy_ticks.fetch_add(1, Ordering::SeqCst);
+                yield_now().await;
+            }
+        }).unwrap();
+        let (sender, receiver) = oneshot::channel::<i32>();
+        let listener = core_system.spawn("listener", None, DEFAULT_PRIORITY, async move {
+            receiver.await.unwrap_or(-1)
+        }).unwrap();
+        let waiter = core_system.wait(listener);
+
+        core_system.schedule().unwrap();
+        assert!(core_system.execute_current().unwrap());
+        assert_eq!(core_system.process_state(busy), Some(ProcessState::Running));
+        assert!(ticks.load(Ordering::SeqCst) > 1);
+
+        // The listener parks on its pending future instead of spinning
+        core_system.schedule().unwrap();
+        assert!(core_system.execute_current().unwrap());
+        assert_eq!(core_system.process_state(listener), Some(ProcessState::Blocked));
+
+        // Delivering the value wakes it up on the next schedule
+        sender.send(7).unwrap();
+        for _ in 0..2 {
+            core_system.schedule().unwrap();
+            core_system.execute_current().unwrap();
+        }
+        assert_eq!(waiter.await.unwrap(), 7);
+        assert_eq!(core_system.process_state(busy), Some(ProcessState::Ready));
+    }
+
+    #[tokio::test]
+    async fn test_run_until_shutdown() {
+        let mut core_system = CoreSystem::new();
+        core_system.set_time_slice(Duration::from_millis(1));
+
+        let worker = core_system.spawn("worker", None, DEFAULT_PRIORITY, async { 0 }).unwrap();
+        let spinner = core_system.spawn("spinner", None, DEFAULT_PRIORITY, async {
+            loop {
+                yield_now().await;
+            }
+        }).unwrap();
+        let (_sender, receiver) = oneshot::channel::<i32>();
+        let sleeper = core_system.spawn("sleeper", None, DEFAULT_PRIORITY, async move {
+            receiver.await.unwrap_or(-2)
+        }).unwrap();
+        let waiter = core_system.wait(spinner);
+
+        let report = core_system
+            .run_until(tokio::time::sleep(Duration::from_millis(20)))
+            .await
+            .unwrap();
+
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].pid, spinner);
+        assert_eq!(report[0].exit_code, SHUTDOWN_EXIT_CODE);
+        assert_eq!(report[1].pid, sleeper);
+        assert_eq!(report[1].state_at_shutdown, ProcessState::Blocked);
+        assert_eq!(core_system.wait(worker).await.unwrap(), 0);
+        assert_eq!(waiter.await.unwrap(), SHUTDOWN_EXIT_CODE);
+        assert!(core_system.process_table().unwrap().is_empty());
+
+        // Stepping after shutdown is a no-op
+        assert!(!core_system.step().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_on_simulated_clock() {
+        use crate::clock::{Clock, SimulatedClock};
+        let clock = SimulatedClock::new();
+        let mut core_system = CoreSystem::new();
+        core_system.set_clock(Arc::new(clock.clone()));
+
+        // Nobody advances the clock, so only idle slices can move it
+        let sleep = clock.sleep(Duration::from_secs(1));
+        let sleeper = core_system.spawn("sleeper", None, DEFAULT_PRIORITY, async move {
+            sleep.await;
+            5
+        }).unwrap();
+        let waiter = core_system.wait(sleeper);
+        let (sender, receiver) = oneshot::channel();
+        let report = core_system.run_until(async move {
+            let _ = sender.send(waiter.await);
+        }).await.unwrap();
+
+        assert_eq!(report[0].exit_code, 5);
+        assert_eq!(receiver.await.unwrap().unwrap(), 5);
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_scheduling_policy() {
+        use crate::scheduler::{EarliestDeadlineFirst, FixedPriority};
+
+        let mut core_system = CoreSystem::new();
+        let low = core_system.create_process_with("telemetry", None, 1).unwrap();
+        let high = core_system.create_process_with("motor-control", None, 5).unwrap();
+        let other_high = core_system.create_process_with("balance", None, 5).unwrap();
+
+        core_system.set_scheduling_policy(Box::new(FixedPriority));
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, high);
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, other_high);
+
+        // Under EDF the process with the earliest deadline wins regardless of priority
+        core_system.set_deadline(low, Duration::from_millis(5)).unwrap();
+        core_system.set_deadline(high, Duration::from_millis(50)).unwrap();
+        core_system.set_scheduling_policy(Box::new(EarliestDeadlineFirst));
+        core_system.schedule().unwrap();
+        assert_eq!(core_system.current_process.as_ref().unwrap().id, low);
+    }
+
+    #[test]
+    fn test_recovery_policy() {
+        let backoff = RecoveryPolicy::Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(25),
+            max_attempts: 3,
+        };
+        assert_eq!(backoff.next_action(1, None), RecoveryAction::Retry(Duration::from_millis(10)));
+        assert_eq!(backoff.next_action(2, None), RecoveryAction::Retry(Duration::from_millis(20)));
+        assert_eq!(backoff.next_action(3, None), RecoveryAction::Retry(Duration::from_millis(25)));
+        assert_eq!(backoff.next_action(4, None), RecoveryAction::Escalate);
+
+        let restart = RecoveryPolicy::RestartProcess { max_attempts: 1 };
+        assert_eq!(restart.next_action(1, Some(3)), RecoveryAction::Restart(3));
+        assert_eq!(restart.next_action(1, None), RecoveryAction::Retry(Duration::ZERO));
+        assert_eq!(RecoveryPolicy::Escalate.next_action(1, Some(3)), RecoveryAction::Escalate);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_escalates() {
+        let mut core_system = CoreSystem::new();
+        core_system.set_recovery_policy(RecoveryPolicy::Escalate);
+        let pid = core_system.spawn("faulty", None, DEFAULT_PRIORITY, async {
+            panic!("sensor driver bug");
+        }).unwrap();
+
+        let error = core_system.run_until(std::future::pending()).await.unwrap_err();
+        assert!(matches!(error, CoreSystemError::SchedulingError { pid: Some(p), .. } if p == pid));
+        assert_eq!(error.source().unwrap().to_string(), format!("PID {} panicked: sensor driver bug", pid));
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_panics() {
+        let mut core_system = CoreSystem::new();
+        core_system.set_recovery_policy(RecoveryPolicy::RestartProcess { max_attempts: 3 });
+        let survivor = core_system.create_process_with("survivor", None, DEFAULT_PRIORITY).unwrap();
+
+        // Fault events are global, so only look at the ones this test caused
+        let since = Instant::now();
+        let faults = |description: &str| {
+            sync::fault_events()
+                .iter()
+                .filter(|fault| fault.at >= since && fault.target == LOG_TARGET && fault.description.starts_with(description))
+                .count()
+        };
+
+        // A panic while the process table is locked poisons it; the table is recovered
+        let processes = Arc::clone(&core_system.processes);
+        let _ = std::thread::spawn(move || {
+            let _guard = processes.lock().unwrap();
+            panic!("poison the process table");
+        }).join();
+        core_system.step().unwrap();
+        assert_eq!(core_system.process_state(survivor), Some(ProcessState::Running));
+        assert_eq!(faults("lock poisoned before schedule"), 1);
+
+        // A panicking process is terminated and restarted without taking the others down
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&attempts);
+        let flaky = core_system.spawn_restartable("flaky", None, DEFAULT_PRIORITY, move || {
+            let attempts = Arc::clone(&counter);
+            async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first start fails");
+                }
+                0
+            }
+        }).unwrap();
+        let waiter = core_system.wait(flaky);
+
+        let report = core_system
+            .run_until(tokio::time::sleep(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        assert_eq!(waiter.await.unwrap(), PANIC_EXIT_CODE);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(faults(&format!("PID {} panicked: first start fails", flaky)), 1);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].pid, survivor);
+    }
+
+    #[tokio::test]
+    async fn test_restart_process() {
+        let mut core_system = CoreSystem::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&starts);
+        let parent = core_system.create_process_with("supervisor", None, DEFAULT_PRIORITY).unwrap();
+        let pid = core_system.spawn_restartable("perception", Some(parent), 4, move || {
+            let starts = Arc::clone(&counter);
+            async move { starts.fetch_add(1, Ordering::SeqCst) as i32 }
+        }).unwrap();
+        core_system.set_affinity(pid, CpuSet::single(1).unwrap()).unwrap();
+
+        let waiter = core_system.wait(pid);
+        let new_pid = core_system.restart_process(pid).unwrap();
+        assert_eq!(waiter.await.unwrap(), RESTART_EXIT_CODE);
+
+        let table = core_system.process_table().unwrap();
+        let restarted = table.iter().find(|info| info.pid == new_pid).unwrap();
+        assert_eq!(restarted.name, "perception");
+        assert_eq!(restarted.parent, Some(parent));
+        assert_eq!(restarted.priority, 4);
+        assert_eq!(restarted.affinity, CpuSet::single(1).unwrap());
+        core_system.set_core(1).unwrap();
+        assert_eq!(table[0].children, vec![new_pid]);
+
+        let waiter = core_system.wait(new_pid);
+        while core_system.process_state(new_pid) != Some(ProcessState::Terminated) {
+            core_system.step().unwrap();
+        }
+        assert_eq!(waiter.await.unwrap(), 0);
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+
+        // A process without a factory is not replaced by one that never runs
+        assert!(matches!(core_system.restart_process(parent), Err(CoreSystemError::NotRestartable { pid }) if pid == parent));
+        assert_ne!(core_system.process_state(parent), Some(ProcessState::Terminated));
+        core_system.set_recovery_policy(RecoveryPolicy::RestartProcess { max_attempts: 3 });
+        let faulty = core_system.spawn("faulty", None, DEFAULT_PRIORITY, async {
+            panic!("no factory to restart from");
+        }).unwrap();
+        let error = core_system.run_until(std::future::pending()).await.unwrap_err();
+        assert!(matches!(error, CoreSystemError::NotRestartable { pid } if pid == faulty));
+    }
+
+    #[test]
+    fn test_process_table() {
+        let ipc = IPC::new();
+        let memory_manager = Arc::new(Mutex::new(MemoryManager::new(1024)));
+        let mut core_system = CoreSystem::with_resources(ipc.clone(), Arc::clone(&memory_manager));
+
+        let parent = core_system.create_process_with("planner", None, 2).unwrap();
+        let child = core_system.create_process_with("perception", Some(parent), 3).unwrap();
+        assert!(core_system.create_process_with("orphan", Some(99), 1).is_err());
+
+        let address = core_system.allocate_memory(child, 512).unwrap();
+        assert!(matches!(
+            core_system.allocate_memory(child, 1024),
+            Err(CoreSystemError::Memory { source: MemoryError::OutOfMemory { requested: 1024, .. }, .. })
+        ));
+        ipc.send_message(parent, child, "scan".to_string()).unwrap();
+        core_system.schedule().unwrap();
+
+        let table = core_system.process_table().unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0].name, "planner");
+        assert_eq!(table[0].children, vec![child]);
+        assert_eq!(table[0].state, ProcessState::Running);
+        assert_eq!(table[0].cpu_slices, 1);
+        assert_eq!(table[1].parent, Some(parent));
+        assert_eq!(table[1].priority, 3);
+        assert_eq!(table[1].memory, vec![address]);
+
+        // Terminating a process releases its memory and mailbox and detaches it from its parent
+        core_system.terminate(child, 0).unwrap();
+        assert!(!ipc.has_mailbox(child));
+        assert!(memory_manager.lock().unwrap().allocate(1024).is_ok());
+        let table = core_system.process_table().unwrap();
+        assert!(table[0].children.is_empty());
+        assert!(table[1].memory.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_block_on_message() {
+        let ipc = IPC::new();
+        let mut core_system = CoreSystem::with_resources(ipc.clone(), Arc::new(Mutex::new(MemoryManager::new(1024))));
+        let mailbox = ipc.clone();
+        let pid = core_system.spawn("listener", None, DEFAULT_PRIORITY, async move {
+            let message = mailbox.recv_async(1).await.unwrap();
+            message.content.len() as i32
+        }).unwrap();
+        assert_eq!(pid, 1);
+        let waiter = core_system.wait(pid);
+
+        core_system.step().unwrap();
+        assert_eq!(core_system.process_state(pid), Some(ProcessState::Blocked));
+        assert_eq!(core_system.process_table().unwrap()[0].blocked_on.as_deref(), Some("message"));
+        // Stays blocked without burning time slices until a message arrives
+        assert!(!core_system.step().unwrap());
+
+        ipc.send_message(0, pid, "go".to_string()).unwrap();
+        core_system.step().unwrap();
+        assert_eq!(waiter.await.unwrap(), 2);
+    }
+}
@@ -1,16 +1,20 @@
+use crate::logging;
+
 pub struct DecisionMaker {
     // TODO: Add fields for decision making components
 }
 
 impl DecisionMaker {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         // TODO: Initialize decision making components
         DecisionMaker {}
     }
 
+    #[allow(dead_code)]
     pub async fn make_decision(&self, context: &str) -> String {
         // TODO: Implement decision making logic
-        println!("Making decision based on context: {}", context);
+        logging::debug("decision_making", &format!("Making decision based on context: {}", context));
         "Decision placeholder".to_string()
     }
 }
\ No newline at end of file
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::error::Error;
+use crate::core_system::CoreSystemError;
+use crate::file_system::FileSystemError;
+use crate::ipc::IpcError;
+use crate::memory_manager::MemoryError;
+use crate::scheduler::SchedulerError;
+
+// Top-level error for code that spans several kernel components
+#[derive(Debug)]
+pub enum MetaRosError {
+    Core(CoreSystemError),
+    Ipc(IpcError),
+    FileSystem(FileSystemError),
+    Memory(MemoryError),
+    Scheduler(SchedulerError),
+}
+
+impl MetaRosError {
+    // The process the failure is attributed to, if any
+    #[allow(dead_code)]
+    pub fn pid(&self) -> Option<u32> {
+        match self {
+            MetaRosError::Core(e) => e.pid(),
+            MetaRosError::Ipc(IpcError::MailboxNotFound(pid)) => Some(*pid),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MetaRosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaRosError::Core(e) => write!(f, "core system: {}", e),
+            MetaRosError::Ipc(e) => write!(f, "ipc: {}", e),
+            MetaRosError::FileSystem(e) => write!(f, "file system: {}", e),
+            MetaRosError::Memory(e) => write!(f, "memory manager: {}", e),
+            MetaRosError::Scheduler(e) => write!(f, "scheduler: {}", e),
+        }
+    }
+}
+
+impl Error for MetaRosError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MetaRosError::Core(e) => Some(e),
+            MetaRosError::Ipc(e) => Some(e),
+            MetaRosError::FileSystem(e) => Some(e),
+            MetaRosError::Memory(e) => Some(e),
+            MetaRosError::Scheduler(e) => Some(e),
+        }
+    }
+}
+
+impl From<CoreSystemError> for MetaRosError {
+    fn from(e: CoreSystemError) -> Self {
+        MetaRosError::Core(e)
+    }
+}
+
+impl From<IpcError> for MetaRosError {
+    fn from(e: IpcError) -> Self {
+        MetaRosError::Ipc(e)
+    }
+}
+
+impl From<FileSystemError> for MetaRosError {
+    fn from(e: FileSystemError) -> Self {
+        MetaRosError::FileSystem(e)
+    }
+}
+
+impl From<MemoryError> for MetaRosError {
+    fn from(e: MemoryError) -> Self {
+        MetaRosError::Memory(e)
+    }
+}
+
+impl From<SchedulerError> for MetaRosError {
+    fn from(e: SchedulerError) -> Self {
+        MetaRosError::Scheduler(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_manager::MemoryManager;
+
+    fn allocate_twice(mm: &mut MemoryManager, size: usize) -> Result<usize, MetaRosError> {
+        mm.allocate(size)?;
+        Ok(mm.allocate(size)?)
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut mm = MemoryManager::new(100);
+        let error = allocate_twice(&mut mm, 60).unwrap_err();
+        assert!(matches!(error, MetaRosError::Memory(MemoryError::OutOfMemory { requested: 60, .. })));
+        assert!(error.to_string().starts_with("memory manager: "));
+        assert!(error.source().is_some());
+
+        let error = MetaRosError::from(CoreSystemError::ProcessNotFound { pid: 4, operation: "block" });
+        assert_eq!(error.pid(), Some(4));
+    }
+}
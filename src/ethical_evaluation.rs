@@ -3,11 +3,13 @@ pub struct EthicalEvaluator {
 }
 
 impl EthicalEvaluator {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         // TODO: Initialize ethical evaluation components
         EthicalEvaluator {}
     }
 
+    #[allow(dead_code)]
     pub async fn evaluate(&self, decision: &str) -> bool {
         // TODO: Implement ethical evaluation logic
         println!("Evaluating decision: {}", decision);
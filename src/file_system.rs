@@ -1,4 +1,26 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum FileSystemError {
+    NotFound(String),
+    NotADirectory(String),
+    NotAFile(String),
+}
+
+impl fmt::Display for FileSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileSystemError::NotFound(path) => write!(f, "File not found: {}", path),
+            FileSystemError::NotADirectory(path) => write!(f, "Directory not found: {}", path),
+            FileSystemError::NotAFile(path) => write!(f, "Not a file: {}", path),
+        }
+    }
+}
+
+impl Error for FileSystemError {}
 
 pub struct File {
     content: Vec<u8>,
@@ -24,44 +46,74 @@ impl FileSystem {
         }
     }
 
-    pub fn create_file(&mut self, path: &str, content: Vec<u8>) -> Result<(), String> {
+    #[allow(dead_code)]
+    pub fn create_file(&mut self, path: &str, content: Vec<u8>) -> Result<(), FileSystemError> {
         let (parent_path, file_name) = self.split_path(path);
-        let parent = self.navigate_to_directory(parent_path)?;
+        let parent = self.navigate_to_directory_mut(parent_path)?;
         
         parent.entries.insert(file_name.to_string(), FileSystemEntry::File(File { content }));
         Ok(())
     }
 
-    pub fn create_directory(&mut self, path: &str) -> Result<(), String> {
+    #[allow(dead_code)]
+    pub fn create_directory(&mut self, path: &str) -> Result<(), FileSystemError> {
         let (parent_path, dir_name) = self.split_path(path);
-        let parent = self.navigate_to_directory(parent_path)?;
+        let parent = self.navigate_to_directory_mut(parent_path)?;
         
         parent.entries.insert(dir_name.to_string(), FileSystemEntry::Directory(Directory { entries: HashMap::new() }));
         Ok(())
     }
 
-    pub fn read_file(&self, path: &str) -> Result<&Vec<u8>, String> {
+    pub fn read_file(&self, path: &str) -> Result<&Vec<u8>, FileSystemError> {
         let (parent_path, file_name) = self.split_path(path);
         let parent = self.navigate_to_directory(parent_path)?;
         
         match parent.entries.get(file_name) {
             Some(FileSystemEntry::File(file)) => Ok(&file.content),
-            _ => Err(format!("File not found: {}", path)),
+            _ => Err(FileSystemError::NotFound(path.to_string())),
+        }
+    }
+
+    // Creates the file if it does not exist yet
+    pub fn append_file(&mut self, path: &str, content: &[u8]) -> Result<(), FileSystemError> {
+        let (parent_path, file_name) = self.split_path(path);
+        let parent = self.navigate_to_directory_mut(parent_path)?;
+
+        match parent.entries
+            .entry(file_name.to_string())
+            .or_insert_with(|| FileSystemEntry::File(File { content: Vec::new() }))
+        {
+            FileSystemEntry::File(file) => {
+                file.content.extend_from_slice(content);
+                Ok(())
+            },
+            FileSystemEntry::Directory(_) => Err(FileSystemError::NotAFile(path.to_string())),
+        }
+    }
+
+    fn navigate_to_directory_mut(&mut self, path: &str) -> Result<&mut Directory, FileSystemError> {
+        let mut current = &mut self.root;
+        for component in path.split('/').filter(|&c| !c.is_empty()) {
+            match current.entries.get_mut(component) {
+                Some(FileSystemEntry::Directory(dir)) => current = dir,
+                _ => return Err(FileSystemError::NotADirectory(path.to_string())),
+            }
         }
+        Ok(current)
     }
 
-    fn navigate_to_directory(&self, path: &str) -> Result<&Directory, String> {
+    fn navigate_to_directory(&self, path: &str) -> Result<&Directory, FileSystemError> {
         let mut current = &self.root;
         for component in path.split('/').filter(|&c| !c.is_empty()) {
             match current.entries.get(component) {
                 Some(FileSystemEntry::Directory(dir)) => current = dir,
-                _ => return Err(format!("Directory not found: {}", path)),
+                _ => return Err(FileSystemError::NotADirectory(path.to_string())),
             }
         }
         Ok(current)
     }
 
-    fn split_path(&self, path: &str) -> (&str, &str) {
+    fn split_path<'a>(&self, path: &'a str) -> (&'a str, &'a str) {
         match path.rfind('/') {
             Some(index) => (&path[..index], &path[index + 1..]),
             None => ("", path),
@@ -89,5 +141,11 @@ mod tests {
 
         assert!(fs.read_file("/nonexistent/file.txt").is_err());
         assert!(fs.create_file("/nonexistent/dir/file.txt", vec![]).is_err());
+
+        fs.append_file("/home/user/test.txt", b" Again.").unwrap();
+        fs.append_file("/home/user/new.log", b"line").unwrap();
+        assert_eq!(fs.read_file("/home/user/test.txt").unwrap(), b"Hello, world! Again.");
+        assert_eq!(fs.read_file("/home/user/new.log").unwrap(), b"line");
+        assert!(fs.append_file("/home/user/documents", b"x").is_err());
     }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
+use std::collections::VecDeque;
 use rand::Rng;
+use crate::logging;
 
 pub trait Sensor {
     fn read(&self) -> f64;
@@ -12,10 +14,17 @@ pub struct MotorController {
     current_speed: f64,
 }
 
+impl MotorController {
+    #[allow(dead_code)]
+    pub fn speed(&self) -> f64 {
+        self.current_speed
+    }
+}
+
 impl Actuator for MotorController {
     fn write(&mut self, value: f64) {
         self.current_speed = value.clamp(-1.0, 1.0);
-        println!("Motor speed set to: {}", self.current_speed);
+        logging::debug("hal", &format!("Motor speed set to: {}", self.current_speed));
     }
 }
 
@@ -30,9 +39,17 @@ impl Sensor for DistanceSensor {
     }
 }
 
+// Something the hardware layer did or noticed that the main loop should know about
+#[derive(Debug, Clone, PartialEq)]
+pub enum HardwareEvent {
+    SafeStop,
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct HAL {
     pub motor: MotorController,
     pub distance_sensor: DistanceSensor,
+    events: VecDeque<HardwareEvent>,
 }
 
 impl HAL {
@@ -40,6 +57,19 @@ impl HAL {
         HAL {
             motor: MotorController { current_speed: 0.0 },
             distance_sensor: DistanceSensor {},
+            events: VecDeque::new(),
         }
     }
+
+    // Oldest hardware event not handled yet
+    pub fn check_events(&mut self) -> Option<HardwareEvent> {
+        self.events.pop_front()
+    }
+
+    // Brings every actuator to a safe state, e.g. on a watchdog expiry
+    pub fn safe_stop(&mut self) {
+        logging::warn("hal", "Safe stop: halting all actuators");
+        self.motor.write(0.0);
+        self.events.push_back(HardwareEvent::SafeStop);
+    }
 }
\ No newline at end of file
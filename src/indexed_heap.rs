@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+// Binary min-heap with a position index, so entries can be looked up,
+// removed or re-ordered by key in O(log n)
+pub struct IndexedHeap<T: Ord> {
+    entries: Vec<(u32, T)>,
+    positions: HashMap<u32, usize>,
+}
+
+impl<T: Ord> IndexedHeap<T> {
+    pub fn new() -> Self {
+        IndexedHeap { entries: Vec::new(), positions: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, key: u32) -> bool {
+        self.positions.contains_key(&key)
+    }
+
+    // Inserts `value` under `key`, returning the value it replaced
+    pub fn push(&mut self, key: u32, value: T) -> Option<T> {
+        let previous = self.remove(key);
+        self.entries.push((key, value));
+        let index = self.entries.len() - 1;
+        self.positions.insert(key, index);
+        self.sift_up(index);
+        previous
+    }
+
+    pub fn peek(&self) -> Option<(u32, &T)> {
+        self.entries.first().map(|(key, value)| (*key, value))
+    }
+
+    pub fn pop(&mut self) -> Option<(u32, T)> {
+        let key = self.entries.first()?.0;
+        self.remove(key).map(|value| (key, value))
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, key: u32) -> Option<&T> {
+        self.positions.get(&key).map(|&index| &self.entries[index].1)
+    }
+
+    pub fn remove(&mut self, key: u32) -> Option<T> {
+        let index = self.positions.remove(&key)?;
+        let last = self.entries.len() - 1;
+        self.entries.swap(index, last);
+        let (_, value) = self.entries.pop()?;
+        if index < self.entries.len() {
+            self.positions.insert(self.entries[index].0, index);
+            self.sift(index);
+        }
+        Some(value)
+    }
+
+    // Modifies the entry under `key` in place and restores the heap order
+    pub fn update<F: FnOnce(&mut T)>(&mut self, key: u32, change: F) -> bool {
+        let Some(&index) = self.positions.get(&key) else {
+            return false;
+        };
+        change(&mut self.entries[index].1);
+        self.sift(index);
+        true
+    }
+
+    // Entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.entries.iter().map(|(key, value)| (*key, value))
+    }
+
+    pub fn keys(&self) -> Vec<u32> {
+        self.entries.iter().map(|(key, _)| *key).collect()
+    }
+
+    fn sift(&mut self, index: usize) {
+        let index = self.sift_up(index);
+        self.sift_down(index);
+    }
+
+    fn sift_up(&mut self, mut index: usize) -> usize {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if self.entries[index].1 >= self.entries[parent].1 {
+                break;
+            }
+            self.swap(index, parent);
+            index = parent;
+        }
+        index
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let mut smallest = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < self.entries.len() && self.entries[child].1 < self.entries[smallest].1 {
+                    smallest = child;
+                }
+            }
+            if smallest == index {
+                return;
+            }
+            self.swap(index, smallest);
+            index = smallest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        self.positions.insert(self.entries[a].0, a);
+        self.positions.insert(self.entries[b].0, b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_heap() {
+        let mut heap = IndexedHeap::new();
+        for (key, value) in [(1, 50), (2, 20), (3, 40), (4, 10), (5, 30)] {
+            heap.push(key, value);
+        }
+        assert_eq!(heap.peek(), Some((4, &10)));
+        assert_eq!(heap.remove(2), Some(20));
+        assert!(heap.update(1, |value| *value = 5));
+        assert!(heap.update(4, |value| *value = 45));
+        assert!(!heap.update(9, |value| *value = 0));
+        assert_eq!(heap.push(3, 35), Some(40));
+        assert_eq!(heap.get(3), Some(&35));
+
+        let mut order = Vec::new();
+        while let Some((key, _)) = heap.pop() {
+            order.push(key);
+        }
+        assert_eq!(order, vec![1, 5, 3, 4]);
+        assert!(heap.is_empty());
+    }
+}
@@ -1,15 +1,19 @@
+use crate::logging;
+
 pub struct InputProcessor {
     // TODO: Add fields for input processing components
 }
 
 impl InputProcessor {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         // TODO: Initialize input processing components
         InputProcessor {}
     }
 
+    #[allow(dead_code)]
     pub async fn process_input(&self, input: &str) {
         // TODO: Implement input processing logic
-        println!("Processing input: {}", input);
+        logging::debug("input_processing", &format!("Processing input: {}", input));
     }
 }
\ No newline at end of file
@@ -1,38 +1,656 @@
+use std::any::type_name;
 use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime};
+use std::fmt;
+use std::error::Error;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::clock::{self, SharedClock};
+use crate::codec::{Codec, CodecError};
+use crate::shared_memory::SharedBuffer;
+use crate::sync::lock_or_recover;
 
+const LOG_TARGET: &str = "ipc";
+
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpcError {
+    MailboxNotFound(u32),
+    Timeout { owner: u32, after: Duration },
+    MailboxFull { owner: u32, capacity: usize },
+    // A typed receive found a message of another type or codec
+    TypeMismatch { expected: String, found: String },
+    Codec(CodecError),
+    // `select` was given no mailboxes to wait on
+    EmptySelect,
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpcError::MailboxNotFound(owner) => write!(f, "No mailbox for PID: {}", owner),
+            IpcError::Timeout { owner, after } => write!(f, "No message for PID {} within {:?}", owner, after),
+            IpcError::MailboxFull { owner, capacity } => write!(f, "Mailbox of PID {} is full ({} messages)", owner, capacity),
+            IpcError::TypeMismatch { expected, found } => write!(f, "Expected a {} message, found {}", expected, found),
+            IpcError::Codec(e) => write!(f, "{}", e),
+            IpcError::EmptySelect => write!(f, "Select needs at least one mailbox"),
+        }
+    }
+}
+
+impl Error for IpcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IpcError::Codec(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CodecError> for IpcError {
+    fn from(e: CodecError) -> Self {
+        IpcError::Codec(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageHeader {
+    // Position in the stream of deliveries of the mailbox that first received
+    // the message; a forwarded message keeps it
+    pub seq: u64,
+    // Time of first delivery, taken from the IPC's clock
+    pub timestamp: SystemTime,
+    // Coordinate frame the data refers to, e.g. "base_link"
+    pub frame_id: String,
+    // Rust type name and codec of the payload; both empty for text messages
+    pub type_tag: String,
+    pub codec: String,
+    // Topic the message was published on; empty for messages sent to a PID
+    pub topic: String,
+}
+
+#[derive(Debug, Clone)]
 pub struct Message {
     pub sender: u32,
+    pub header: MessageHeader,
     pub content: String,
+    pub payload: Vec<u8>,
+    // Large payloads travel as a handle to shared memory instead of in `payload`
+    pub buffer: Option<SharedBuffer>,
+}
+
+impl Message {
+    pub fn text(sender: u32, content: String) -> Self {
+        Message {
+            sender,
+            header: MessageHeader {
+                seq: 0,
+                timestamp: SystemTime::UNIX_EPOCH,
+                frame_id: String::new(),
+                type_tag: String::new(),
+                codec: String::new(),
+                topic: String::new(),
+            },
+            content,
+            payload: Vec::new(),
+            buffer: None,
+        }
+    }
+
+    // A binary message carrying `value` encoded with `C`
+    pub fn encode<T: Serialize, C: Codec>(sender: u32, frame_id: &str, value: &T) -> Result<Self, IpcError> {
+        let mut message = Message::text(sender, String::new());
+        message.header.frame_id = frame_id.to_string();
+        message.header.type_tag = type_name::<T>().to_string();
+        message.header.codec = C::NAME.to_string();
+        message.payload = C::encode(value)?;
+        Ok(message)
+    }
+
+    // A message handing `buffer` to the recipient without copying its bytes
+    pub fn shared(sender: u32, frame_id: &str, buffer: SharedBuffer) -> Self {
+        let mut message = Message::text(sender, String::new());
+        message.header.frame_id = frame_id.to_string();
+        message.header.type_tag = type_name::<SharedBuffer>().to_string();
+        message.header.codec = SHARED_MEMORY_CODEC.to_string();
+        message.buffer = Some(buffer);
+        message
+    }
+
+    pub fn is_typed<T, C: Codec>(&self) -> bool {
+        self.header.type_tag == type_name::<T>() && self.header.codec == C::NAME
+    }
+
+    pub fn decode<T: DeserializeOwned, C: Codec>(&self) -> Result<T, IpcError> {
+        if !self.is_typed::<T, C>() {
+            return Err(self.mismatch(format!("{} ({})", type_name::<T>(), C::NAME)));
+        }
+        Ok(C::decode(&self.payload)?)
+    }
+
+    fn mismatch(&self, expected: String) -> IpcError {
+        IpcError::TypeMismatch {
+            expected,
+            found: if self.header.type_tag.is_empty() {
+                "text".to_string()
+            } else {
+                format!("{} ({})", self.header.type_tag, self.header.codec)
+            },
+        }
+    }
+}
+
+// Codec name in the header of messages that carry a shared-memory buffer
+pub const SHARED_MEMORY_CODEC: &str = "shared_memory";
+
+// What a send does when the recipient's mailbox is at capacity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    // `send_async` waits for room; `send_message` fails with MailboxFull
+    #[allow(dead_code)]
+    Block,
+    DropOldest,
+    #[allow(dead_code)]
+    DropNewest,
+    #[allow(dead_code)]
+    Error,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailboxStats {
+    pub owner: u32,
+    pub depth: usize,
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+    // Deepest the queue has been
+    pub high_watermark: usize,
+    pub delivered: u64,
+    // Messages discarded by DropOldest or DropNewest
+    pub dropped: u64,
+    // Sends refused with MailboxFull
+    pub rejected: u64,
+}
+
+struct Mailbox {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    high_watermark: usize,
+    // Total number of messages ever delivered, so waiters can tell that something arrived
+    delivered: u64,
+    dropped: u64,
+    rejected: u64,
+    // Receivers waiting for the next delivery, by waiter id
+    wakers: HashMap<u64, Waker>,
+    // Blocked senders waiting for room, by waiter id
+    senders: HashMap<u64, Waker>,
+}
+
+// Each pending future holds one slot, updated in place when it is polled
+// again and removed when it is dropped
+fn register(wakers: &mut HashMap<u64, Waker>, id: u64, waker: &Waker) {
+    match wakers.get_mut(&id) {
+        Some(registered) if registered.will_wake(waker) => {},
+        Some(registered) => *registered = waker.clone(),
+        None => {
+            wakers.insert(id, waker.clone());
+        },
+    }
+}
+
+fn wake_all(wakers: HashMap<u64, Waker>) {
+    for waker in wakers.into_values() {
+        waker.wake();
+    }
+}
+
+impl Mailbox {
+    fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Mailbox {
+            messages: VecDeque::new(),
+            capacity,
+            policy,
+            high_watermark: 0,
+            delivered: 0,
+            dropped: 0,
+            rejected: 0,
+            wakers: HashMap::new(),
+            senders: HashMap::new(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.messages.len() >= self.capacity
+    }
+
+    // Discards messages beyond `capacity`: the oldest under DropOldest, the
+    // newest under any other policy
+    fn trim(&mut self) -> usize {
+        let excess = self.messages.len().saturating_sub(self.capacity);
+        match self.policy {
+            OverflowPolicy::DropOldest => drop(self.messages.drain(..excess)),
+            _ => self.messages.truncate(self.capacity),
+        }
+        self.dropped += excess as u64;
+        excess
+    }
+
+    // Hands the message back when the sender should wait for room
+    fn push(&mut self, owner: u32, mut message: Message, now: SystemTime, wait: bool) -> Result<Option<Message>, IpcError> {
+        if self.is_full() {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    let excess = self.messages.len() + 1 - self.capacity;
+                    self.messages.drain(..excess);
+                    self.dropped += excess as u64;
+                },
+                OverflowPolicy::DropNewest => {
+                    self.dropped += 1;
+                    return Ok(None);
+                },
+                OverflowPolicy::Block if wait => return Ok(Some(message)),
+                OverflowPolicy::Block | OverflowPolicy::Error => {
+                    self.rejected += 1;
+                    return Err(IpcError::MailboxFull { owner, capacity: self.capacity });
+                },
+            }
+        }
+        // A forwarded message keeps the sequence number and time of its first delivery
+        if message.header.seq == 0 {
+            message.header.seq = self.delivered + 1;
+            message.header.timestamp = now;
+        }
+        self.messages.push_back(message);
+        self.high_watermark = self.high_watermark.max(self.messages.len());
+        self.delivered += 1;
+        Ok(None)
+    }
+
+    // Takes the message at `index` and returns the senders to wake now that there is room
+    fn remove(&mut self, index: usize) -> Option<(Message, HashMap<u64, Waker>)> {
+        let message = self.messages.remove(index)?;
+        Some((message, std::mem::take(&mut self.senders)))
+    }
+
+    fn stats(&self, owner: u32) -> MailboxStats {
+        MailboxStats {
+            owner,
+            depth: self.messages.len(),
+            capacity: self.capacity,
+            policy: self.policy,
+            high_watermark: self.high_watermark,
+            delivered: self.delivered,
+            dropped: self.dropped,
+            rejected: self.rejected,
+        }
+    }
+}
+
+// Mailboxes exist only for registered owners (normally CoreSystem processes)
+// and hold at most `capacity` messages each
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct IPC {
-    mailboxes: Arc<Mutex<HashMap<u32, VecDeque<Message>>>>,
+    mailboxes: Arc<Mutex<HashMap<u32, Mailbox>>>,
+    defaults: Arc<Mutex<(usize, OverflowPolicy)>>,
+    clock: Arc<Mutex<SharedClock>>,
+    next_waiter: Arc<AtomicU64>,
 }
 
 impl IPC {
     pub fn new() -> Self {
         IPC {
             mailboxes: Arc::new(Mutex::new(HashMap::new())),
+            defaults: Arc::new(Mutex::new((DEFAULT_MAILBOX_CAPACITY, OverflowPolicy::DropOldest))),
+            clock: Arc::new(Mutex::new(clock::system_clock())),
+            next_waiter: Arc::new(AtomicU64::new(1)),
         }
     }
 
-    pub fn send_message(&self, sender: u32, recipient: u32, content: String) {
-        let message = Message { sender, content };
-        self.mailboxes
-            .lock()
-            .unwrap()
-            .entry(recipient)
-            .or_insert_with(VecDeque::new)
-            .push_back(message);
+    // Clock used to timestamp messages; shared by every clone of this IPC
+    pub fn set_clock(&self, clock: SharedClock) {
+        *lock_or_recover(&self.clock, LOG_TARGET, "set_clock") = clock;
+    }
+
+    pub fn clock(&self) -> SharedClock {
+        lock_or_recover(&self.clock, LOG_TARGET, "clock").clone()
+    }
+
+    // Capacity and overflow policy of mailboxes created from now on
+    #[allow(dead_code)]
+    pub fn set_default_limits(&self, capacity: usize, policy: OverflowPolicy) {
+        *lock_or_recover(&self.defaults, LOG_TARGET, "set_default_limits") = (capacity.max(1), policy);
+    }
+
+    // Changes the limits of an existing mailbox. Messages queued beyond a
+    // reduced capacity are discarded as the new policy would; returns how many.
+    #[allow(dead_code)]
+    pub fn set_limits(&self, owner: u32, capacity: usize, policy: OverflowPolicy) -> Result<usize, IpcError> {
+        let mut mailboxes = lock_or_recover(&self.mailboxes, LOG_TARGET, "set_limits");
+        let mailbox = mailboxes.get_mut(&owner).ok_or(IpcError::MailboxNotFound(owner))?;
+        mailbox.capacity = capacity.max(1);
+        mailbox.policy = policy;
+        let trimmed = mailbox.trim();
+        let senders = std::mem::take(&mut mailbox.senders);
+        drop(mailboxes);
+        wake_all(senders);
+        Ok(trimmed)
+    }
+
+    // Queues a message without waiting. Fails if `recipient` has no mailbox or
+    // its full mailbox uses the Block or Error policy.
+    pub fn send_message(&self, sender: u32, recipient: u32, content: String) -> Result<(), IpcError> {
+        self.deliver(recipient, Message::text(sender, content), false).map(|_| ())
+    }
+
+    // Sends `value` as a binary message encoded with `C`, e.g. `send::<PointCloud, BincodeCodec>`
+    #[allow(dead_code)]
+    pub fn send<T: Serialize, C: Codec>(&self, sender: u32, recipient: u32, frame_id: &str, value: &T) -> Result<(), IpcError> {
+        let message = Message::encode::<T, C>(sender, frame_id, value)?;
+        self.deliver(recipient, message, false).map(|_| ())
+    }
+
+    // Like `send_message`, but waits for room in a full mailbox with the Block policy
+    #[allow(dead_code)]
+    pub async fn send_async(&self, sender: u32, recipient: u32, content: String) -> Result<(), IpcError> {
+        let mut message = Message::text(sender, content);
+        while let Some(returned) = self.deliver(recipient, message, true)? {
+            message = returned;
+            Room { ipc: self.clone(), id: self.waiter_id(), recipient }.await?;
+        }
+        Ok(())
+    }
+
+    // Queues an already built message, e.g. one that arrived over a transport.
+    // Only a message that was never delivered gets a sequence number and timestamp.
+    pub fn post(&self, recipient: u32, message: Message) -> Result<(), IpcError> {
+        self.deliver(recipient, message, false).map(|_| ())
+    }
+
+    fn deliver(&self, recipient: u32, message: Message, wait: bool) -> Result<Option<Message>, IpcError> {
+        let now = self.clock().system_time();
+        let mut mailboxes = lock_or_recover(&self.mailboxes, LOG_TARGET, "send_message");
+        let mailbox = mailboxes.get_mut(&recipient).ok_or(IpcError::MailboxNotFound(recipient))?;
+        if let Some(message) = mailbox.push(recipient, message, now, wait)? {
+            return Ok(Some(message));
+        }
+        let wakers = std::mem::take(&mut mailbox.wakers);
+        // Wake receivers without holding the lock, they may poll straight away
+        drop(mailboxes);
+        wake_all(wakers);
+        Ok(None)
     }
 
     pub fn receive_message(&self, recipient: u32) -> Option<Message> {
-        self.mailboxes
-            .lock()
-            .unwrap()
-            .get_mut(&recipient)
-            .and_then(|mailbox| mailbox.pop_front())
+        self.take_message(recipient, |_| true)
+    }
+
+    // Takes the next message if it carries a `T` encoded with `C`. A message of
+    // any other type is left at the head of the mailbox and reported as a
+    // TypeMismatch, so it can still be received with the right type.
+    #[allow(dead_code)]
+    pub fn recv<T: DeserializeOwned, C: Codec>(&self, recipient: u32) -> Result<Option<(MessageHeader, T)>, IpcError> {
+        self.take_head(recipient, |message| message.decode::<T, C>())
+            .map(|taken| taken.map(|(message, value)| (message.header, value)))
+    }
+
+    #[allow(dead_code)]
+    pub fn send_buffer(&self, sender: u32, recipient: u32, frame_id: &str, buffer: SharedBuffer) -> Result<(), IpcError> {
+        self.deliver(recipient, Message::shared(sender, frame_id, buffer), false).map(|_| ())
+    }
+
+    // Takes the next message if it carries a shared-memory buffer; other
+    // messages are left in place as with `recv`
+    #[allow(dead_code)]
+    pub fn recv_buffer(&self, recipient: u32) -> Result<Option<(MessageHeader, SharedBuffer)>, IpcError> {
+        let taken = self.take_head(recipient, |message| match message.buffer {
+            Some(_) => Ok(()),
+            None => Err(message.mismatch(format!("{} ({})", type_name::<SharedBuffer>(), SHARED_MEMORY_CODEC))),
+        })?;
+        Ok(taken.and_then(|(message, _)| message.buffer.map(|buffer| (message.header, buffer))))
+    }
+
+    // Removes the head message if `accept` succeeds on it
+    fn take_head<R, F>(&self, recipient: u32, accept: F) -> Result<Option<(Message, R)>, IpcError>
+    where
+        F: FnOnce(&Message) -> Result<R, IpcError>,
+    {
+        let mut mailboxes = lock_or_recover(&self.mailboxes, LOG_TARGET, "recv");
+        let mailbox = mailboxes.get_mut(&recipient).ok_or(IpcError::MailboxNotFound(recipient))?;
+        let Some(message) = mailbox.messages.front() else {
+            return Ok(None);
+        };
+        let accepted = accept(message)?;
+        let (message, senders) = mailbox.remove(0).expect("the mailbox has a head message");
+        drop(mailboxes);
+        wake_all(senders);
+        Ok(Some((message, accepted)))
+    }
+
+    // Like `receive_message`, but tells a missing mailbox apart from an empty one
+    pub fn try_recv(&self, recipient: u32) -> Result<Option<Message>, IpcError> {
+        if !self.has_mailbox(recipient) {
+            return Err(IpcError::MailboxNotFound(recipient));
+        }
+        Ok(self.receive_message(recipient))
+    }
+
+    // Resolves with the next message for `recipient`. A process body awaiting
+    // this is parked by the CoreSystem and woken when a message is delivered.
+    pub async fn recv_async(&self, recipient: u32) -> Result<Message, IpcError> {
+        self.select(&[recipient]).await.map(|(_, message)| message)
+    }
+
+    // The timeout runs on the IPC's clock, so it follows a SimulatedClock
+    #[allow(dead_code)]
+    pub async fn recv_timeout(&self, recipient: u32, timeout: Duration) -> Result<Message, IpcError> {
+        let expired = self.clock().sleep(timeout);
+        tokio::select! {
+            biased;
+            result = self.recv_async(recipient) => result,
+            _ = expired => Err(IpcError::Timeout { owner: recipient, after: timeout }),
+        }
+    }
+
+    // Resolves with the first message delivered to any of `recipients`, along
+    // with the mailbox it was taken from. Earlier mailboxes win ties; an empty
+    // list fails with EmptySelect.
+    pub fn select(&self, recipients: &[u32]) -> Recv {
+        Recv { ipc: self.clone(), id: self.waiter_id(), recipients: recipients.to_vec() }
+    }
+
+    // Resolves once a message is delivered to `recipient` after this call,
+    // without taking it out of the mailbox
+    pub fn next_delivery(&self, recipient: u32) -> Delivery {
+        let seen = lock_or_recover(&self.mailboxes, LOG_TARGET, "next_delivery")
+            .get(&recipient)
+            .map_or(0, |mailbox| mailbox.delivered);
+        Delivery { ipc: self.clone(), id: self.waiter_id(), recipient, seen }
+    }
+
+    fn waiter_id(&self) -> u64 {
+        self.next_waiter.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Gives up the waker slots of a dropped future
+    fn unregister(&self, recipients: &[u32], id: u64, sender: bool) {
+        let mut mailboxes = lock_or_recover(&self.mailboxes, LOG_TARGET, "unregister");
+        for recipient in recipients {
+            if let Some(mailbox) = mailboxes.get_mut(recipient) {
+                if sender {
+                    mailbox.senders.remove(&id);
+                } else {
+                    mailbox.wakers.remove(&id);
+                }
+            }
+        }
+    }
+
+    // Whether some receiver is currently waiting on the mailbox of `owner`
+    pub fn has_waiters(&self, owner: u32) -> bool {
+        lock_or_recover(&self.mailboxes, LOG_TARGET, "has_waiters")
+            .get(&owner)
+            .is_some_and(|mailbox| !mailbox.wakers.is_empty())
+    }
+
+    // Removes and returns the first message in the mailbox that matches `predicate`,
+    // leaving the others in order
+    pub fn take_message<F>(&self, recipient: u32, predicate: F) -> Option<Message>
+    where
+        F: FnMut(&Message) -> bool,
+    {
+        let mut mailboxes = lock_or_recover(&self.mailboxes, LOG_TARGET, "take_message");
+        let mailbox = mailboxes.get_mut(&recipient)?;
+        let index = mailbox.messages.iter().position(predicate)?;
+        let (message, senders) = mailbox.remove(index)?;
+        drop(mailboxes);
+        wake_all(senders);
+        Some(message)
+    }
+
+    // Creates the mailbox with the default limits; an existing mailbox is kept
+    pub fn create_mailbox(&self, owner: u32) {
+        let (capacity, policy) = *lock_or_recover(&self.defaults, LOG_TARGET, "create_mailbox");
+        lock_or_recover(&self.mailboxes, LOG_TARGET, "create_mailbox")
+            .entry(owner)
+            .or_insert_with(|| Mailbox::new(capacity, policy));
+    }
+
+    // Returns the number of undelivered messages that were discarded. Pending
+    // receivers are woken and fail with MailboxNotFound.
+    pub fn remove_mailbox(&self, owner: u32) -> Result<usize, IpcError> {
+        let mailbox = lock_or_recover(&self.mailboxes, LOG_TARGET, "remove_mailbox")
+            .remove(&owner)
+            .ok_or(IpcError::MailboxNotFound(owner))?;
+        wake_all(mailbox.wakers);
+        wake_all(mailbox.senders);
+        Ok(mailbox.messages.len())
+    }
+
+    pub fn has_mailbox(&self, owner: u32) -> bool {
+        lock_or_recover(&self.mailboxes, LOG_TARGET, "has_mailbox").contains_key(&owner)
+    }
+
+    pub fn mailbox_stats(&self, owner: u32) -> Option<MailboxStats> {
+        lock_or_recover(&self.mailboxes, LOG_TARGET, "mailbox_stats")
+            .get(&owner)
+            .map(|mailbox| mailbox.stats(owner))
+    }
+
+    // Queue-depth metrics of every mailbox, by owner
+    pub fn stats(&self) -> Vec<MailboxStats> {
+        let mut stats: Vec<MailboxStats> = lock_or_recover(&self.mailboxes, LOG_TARGET, "stats")
+            .iter()
+            .map(|(owner, mailbox)| mailbox.stats(*owner))
+            .collect();
+        stats.sort_by_key(|stats| stats.owner);
+        stats
+    }
+}
+
+// Future returned by `select`
+pub struct Recv {
+    ipc: IPC,
+    id: u64,
+    recipients: Vec<u32>,
+}
+
+impl Future for Recv {
+    type Output = Result<(u32, Message), IpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.recipients.is_empty() {
+            return Poll::Ready(Err(IpcError::EmptySelect));
+        }
+        let mut mailboxes = lock_or_recover(&self.ipc.mailboxes, LOG_TARGET, "recv");
+        for &recipient in &self.recipients {
+            let mailbox = match mailboxes.get_mut(&recipient) {
+                Some(mailbox) => mailbox,
+                None => return Poll::Ready(Err(IpcError::MailboxNotFound(recipient))),
+            };
+            if let Some((message, senders)) = mailbox.remove(0) {
+                drop(mailboxes);
+                wake_all(senders);
+                return Poll::Ready(Ok((recipient, message)));
+            }
+        }
+        for recipient in &self.recipients {
+            if let Some(mailbox) = mailboxes.get_mut(recipient) {
+                register(&mut mailbox.wakers, self.id, cx.waker());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Recv {
+    fn drop(&mut self) {
+        self.ipc.unregister(&self.recipients, self.id, false);
+    }
+}
+
+// Future returned by `next_delivery`
+pub struct Delivery {
+    ipc: IPC,
+    id: u64,
+    recipient: u32,
+    seen: u64,
+}
+
+impl Future for Delivery {
+    type Output = Result<(), IpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut mailboxes = lock_or_recover(&self.ipc.mailboxes, LOG_TARGET, "next_delivery");
+        match mailboxes.get_mut(&self.recipient) {
+            None => Poll::Ready(Err(IpcError::MailboxNotFound(self.recipient))),
+            Some(mailbox) if mailbox.delivered > self.seen => Poll::Ready(Ok(())),
+            Some(mailbox) => {
+                register(&mut mailbox.wakers, self.id, cx.waker());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl Drop for Delivery {
+    fn drop(&mut self) {
+        self.ipc.unregister(&[self.recipient], self.id, false);
+    }
+}
+
+// Resolves once a full mailbox has room again
+struct Room {
+    ipc: IPC,
+    id: u64,
+    recipient: u32,
+}
+
+impl Future for Room {
+    type Output = Result<(), IpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut mailboxes = lock_or_recover(&self.ipc.mailboxes, LOG_TARGET, "send_async");
+        match mailboxes.get_mut(&self.recipient) {
+            None => Poll::Ready(Err(IpcError::MailboxNotFound(self.recipient))),
+            Some(mailbox) if !mailbox.is_full() => Poll::Ready(Ok(())),
+            Some(mailbox) => {
+                register(&mut mailbox.senders, self.id, cx.waker());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl Drop for Room {
+    fn drop(&mut self) {
+        self.ipc.unregister(&[self.recipient], self.id, true);
     }
 }
 
@@ -43,9 +661,11 @@ mod tests {
     #[test]
     fn test_ipc() {
         let ipc = IPC::new();
+        ipc.create_mailbox(2);
+        ipc.create_mailbox(4);
 
-        ipc.send_message(1, 2, "Hello".to_string());
-        ipc.send_message(3, 2, "World".to_string());
+        ipc.send_message(1, 2, "Hello".to_string()).unwrap();
+        ipc.send_message(3, 2, "World".to_string()).unwrap();
 
         let msg1 = ipc.receive_message(2).unwrap();
         assert_eq!(msg1.sender, 1);
@@ -56,5 +676,173 @@ mod tests {
         assert_eq!(msg2.content, "World");
 
         assert!(ipc.receive_message(2).is_none());
+
+        ipc.send_message(1, 2, "first".to_string()).unwrap();
+        ipc.send_message(1, 2, "second".to_string()).unwrap();
+        assert_eq!(ipc.take_message(2, |m| m.content == "second").unwrap().content, "second");
+        assert!(ipc.take_message(2, |m| m.content == "third").is_none());
+        assert_eq!(ipc.receive_message(2).unwrap().content, "first");
+
+        ipc.send_message(1, 4, "Unread".to_string()).unwrap();
+        assert_eq!(ipc.remove_mailbox(4), Ok(1));
+        assert_eq!(ipc.remove_mailbox(4), Err(IpcError::MailboxNotFound(4)));
+        assert_eq!(ipc.send_message(1, 4, "Lost".to_string()), Err(IpcError::MailboxNotFound(4)));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let ipc = IPC::new();
+        ipc.set_default_limits(2, OverflowPolicy::DropOldest);
+        for owner in 1..=4 {
+            ipc.create_mailbox(owner);
+        }
+        ipc.set_limits(2, 2, OverflowPolicy::DropNewest).unwrap();
+        ipc.set_limits(3, 2, OverflowPolicy::Error).unwrap();
+        ipc.set_limits(4, 1, OverflowPolicy::Block).unwrap();
+
+        for owner in 1..=3 {
+            for index in 0..3 {
+                let sent = ipc.send_message(0, owner, index.to_string());
+                assert_eq!(sent.is_err(), owner == 3 && index == 2);
+            }
+        }
+        assert_eq!(ipc.receive_message(1).unwrap().content, "1");
+        assert_eq!(ipc.receive_message(2).unwrap().content, "0");
+        assert_eq!(ipc.send_message(0, 3, "x".to_string()), Err(IpcError::MailboxFull { owner: 3, capacity: 2 }));
+
+        let stats = ipc.stats();
+        assert_eq!((stats[0].dropped, stats[0].high_watermark), (1, 2));
+        assert_eq!((stats[1].dropped, stats[1].depth), (1, 1));
+        assert_eq!((stats[2].rejected, stats[2].delivered), (2, 2));
+
+        // A blocked sender resumes once the receiver makes room
+        ipc.send_message(0, 4, "first".to_string()).unwrap();
+        assert!(ipc.send_message(0, 4, "now".to_string()).is_err());
+        let sender = tokio::spawn({
+            let ipc = ipc.clone();
+            async move { ipc.send_async(0, 4, "second".to_string()).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!sender.is_finished());
+        assert_eq!(ipc.receive_message(4).unwrap().content, "first");
+        sender.await.unwrap().unwrap();
+        assert_eq!(ipc.receive_message(4).unwrap().content, "second");
+        assert_eq!(ipc.mailbox_stats(4).unwrap().rejected, 1);
+
+        // Shrinking a mailbox discards what no longer fits
+        ipc.set_limits(1, 3, OverflowPolicy::DropOldest).unwrap();
+        for index in 2..4 {
+            ipc.send_message(0, 1, index.to_string()).unwrap();
+        }
+        assert_eq!(ipc.set_limits(1, 1, OverflowPolicy::DropOldest), Ok(2));
+        assert_eq!(ipc.receive_message(1).unwrap().content, "3");
+        assert_eq!(ipc.mailbox_stats(1).unwrap().dropped, 3);
+
+        // A forwarded message keeps its original sequence number
+        ipc.send_message(0, 2, "forward".to_string()).unwrap();
+        let forwarded = ipc.take_message(2, |message| message.content == "forward").unwrap();
+        ipc.post(1, forwarded.clone()).unwrap();
+        assert_eq!(ipc.receive_message(1).unwrap().header, forwarded.header);
+    }
+
+    #[tokio::test]
+    async fn test_async_receive() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(1);
+        ipc.create_mailbox(2);
+        assert!(ipc.try_recv(1).unwrap().is_none());
+        assert_eq!(ipc.try_recv(3).err(), Some(IpcError::MailboxNotFound(3)));
+
+        let receiver = tokio::spawn({
+            let ipc = ipc.clone();
+            async move { ipc.recv_async(1).await.unwrap().content }
+        });
+        tokio::task::yield_now().await;
+        assert!(ipc.has_waiters(1));
+        ipc.send_message(2, 1, "ping".to_string()).unwrap();
+        assert_eq!(receiver.await.unwrap(), "ping");
+
+        ipc.send_message(1, 2, "pong".to_string()).unwrap();
+        let (mailbox, message) = ipc.select(&[1, 2]).await.unwrap();
+        assert_eq!((mailbox, message.content.as_str()), (2, "pong"));
+
+        let timeout = ipc.recv_timeout(1, Duration::from_millis(5)).await;
+        assert!(matches!(timeout, Err(IpcError::Timeout { owner: 1, .. })));
+        // The abandoned receive no longer counts as a waiter
+        assert!(!ipc.has_waiters(1));
+        assert_eq!(ipc.select(&[]).await.err(), Some(IpcError::EmptySelect));
+
+        // Timeouts follow the IPC's clock rather than wall time
+        let clock = crate::clock::SimulatedClock::new();
+        ipc.set_clock(Arc::new(clock.clone()));
+        let waiting = tokio::spawn({
+            let ipc = ipc.clone();
+            async move { ipc.recv_timeout(1, Duration::from_secs(3600)).await.err() }
+        });
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(waiting.await.unwrap(), Some(IpcError::Timeout { owner: 1, after: Duration::from_secs(3600) }));
+
+        // Removing the mailbox fails a pending receive instead of leaving it hanging
+        let orphan = tokio::spawn({
+            let ipc = ipc.clone();
+            async move { ipc.recv_async(2).await.err() }
+        });
+        tokio::task::yield_now().await;
+        ipc.remove_mailbox(2).unwrap();
+        assert_eq!(orphan.await.unwrap(), Some(IpcError::MailboxNotFound(2)));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct PointCloud {
+        points: Vec<(f32, f32, f32)>,
+    }
+
+    #[test]
+    fn test_typed_messages() {
+        use crate::codec::{BincodeCodec, JsonCodec};
+
+        let ipc = IPC::new();
+        let clock = crate::clock::SimulatedClock::new();
+        clock.advance(Duration::from_secs(5));
+        ipc.set_clock(Arc::new(clock));
+        ipc.create_mailbox(2);
+        let cloud = PointCloud { points: vec![(0.5, 1.0, -2.0), (3.0, 0.0, 0.25)] };
+        ipc.send::<PointCloud, BincodeCodec>(1, 2, "lidar", &cloud).unwrap();
+        ipc.send::<u32, JsonCodec>(1, 2, "", &7).unwrap();
+
+        let (header, received) = ipc.recv::<PointCloud, BincodeCodec>(2).unwrap().unwrap();
+        assert_eq!(received, cloud);
+        assert_eq!((header.seq, header.frame_id.as_str(), header.codec.as_str()), (1, "lidar", "bincode"));
+        assert_eq!(header.timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(5));
+
+        // A mismatched receive fails without consuming the message
+        assert!(matches!(ipc.recv::<u32, BincodeCodec>(2), Err(IpcError::TypeMismatch { .. })));
+        assert!(matches!(ipc.recv::<String, JsonCodec>(2), Err(IpcError::TypeMismatch { .. })));
+        let message = ipc.receive_message(2).unwrap();
+        assert_eq!(message.header.seq, 2);
+        assert_eq!(message.decode::<u32, JsonCodec>(), Ok(7));
+
+        ipc.send_message(1, 2, "plain".to_string()).unwrap();
+        let error = ipc.recv::<u32, JsonCodec>(2).unwrap_err();
+        assert_eq!(error.to_string(), "Expected a u32 (json) message, found text");
+        assert_eq!(ipc.recv::<u32, JsonCodec>(3).err(), Some(IpcError::MailboxNotFound(3)));
+    }
+
+    #[test]
+    fn test_ipc_survives_panicking_sender() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(2);
+        ipc.send_message(1, 2, "Before".to_string()).unwrap();
+
+        let mailboxes = Arc::clone(&ipc.mailboxes);
+        let _ = std::thread::spawn(move || {
+            let _guard = mailboxes.lock().unwrap();
+            panic!("sender panicked mid-send");
+        }).join();
+
+        ipc.send_message(3, 2, "After".to_string()).unwrap();
+        assert_eq!(ipc.receive_message(2).unwrap().content, "Before");
+        assert_eq!(ipc.receive_message(2).unwrap().content, "After");
     }
 }
\ No newline at end of file
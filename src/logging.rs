@@ -0,0 +1,327 @@
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::file_system::FileSystem;
+use crate::sync::lock_or_recover;
+use crate::topics::Publisher;
+
+const LOG_TARGET: &str = "logging";
+
+pub const DEFAULT_RING_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    // Monotonic time since the logger was created
+    pub timestamp: Duration,
+    pub level: Level,
+    pub target: String,
+    pub pid: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}] {:<5} {}",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.level,
+            self.target
+        )?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={}", pid)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+pub trait LogSink: Send {
+    fn write(&mut self, record: &LogRecord);
+}
+
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write(&mut self, record: &LogRecord) {
+        eprintln!("{}", record);
+    }
+}
+
+// Appends one line per record to a file in the MetaROS file system
+pub struct FileSink {
+    file_system: Arc<Mutex<FileSystem>>,
+    path: String,
+}
+
+impl FileSink {
+    #[allow(dead_code)]
+    pub fn new(file_system: Arc<Mutex<FileSystem>>, path: &str) -> Self {
+        FileSink { file_system, path: path.to_string() }
+    }
+}
+
+impl LogSink for FileSink {
+    fn write(&mut self, record: &LogRecord) {
+        let mut file_system = lock_or_recover(&self.file_system, LOG_TARGET, "FileSink::write");
+        let _ = file_system.append_file(&self.path, format!("{}\n", record).as_bytes());
+    }
+}
+
+// Publishes each record on a topic, e.g. for a remote log collector
+pub struct TopicSink {
+    publisher: Publisher<LogRecord>,
+}
+
+impl TopicSink {
+    #[allow(dead_code)]
+    pub fn new(publisher: Publisher<LogRecord>) -> Self {
+        TopicSink { publisher }
+    }
+}
+
+impl LogSink for TopicSink {
+    fn write(&mut self, record: &LogRecord) {
+        // Failures cannot be logged from inside a sink
+        let _ = self.publisher.publish(record);
+    }
+}
+
+type SharedSink = Arc<Mutex<Box<dyn LogSink>>>;
+
+// Returned by `Logger::add_sink` to remove the sink again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SinkId(u64);
+
+struct LoggerState {
+    level: Level,
+    target_levels: HashMap<String, Level>,
+    ring: VecDeque<LogRecord>,
+    capacity: usize,
+    sinks: Vec<(SinkId, SharedSink)>,
+    next_sink: u64,
+}
+
+pub struct Logger {
+    start: Instant,
+    state: Mutex<LoggerState>,
+}
+
+impl Logger {
+    pub fn new(capacity: usize) -> Self {
+        Logger {
+            start: Instant::now(),
+            state: Mutex::new(LoggerState {
+                level: Level::Info,
+                target_levels: HashMap::new(),
+                ring: VecDeque::with_capacity(capacity),
+                capacity,
+                sinks: Vec::new(),
+                next_sink: 1,
+            }),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_level(&self, level: Level) {
+        lock_or_recover(&self.state, LOG_TARGET, "set_level").level = level;
+    }
+
+    // Overrides the global level for one module target
+    #[allow(dead_code)]
+    pub fn set_target_level(&self, target: &str, level: Level) {
+        lock_or_recover(&self.state, LOG_TARGET, "set_target_level").target_levels.insert(target.to_string(), level);
+    }
+
+    pub fn add_sink(&self, sink: Box<dyn LogSink>) -> SinkId {
+        let mut state = lock_or_recover(&self.state, LOG_TARGET, "add_sink");
+        let id = SinkId(state.next_sink);
+        state.next_sink += 1;
+        state.sinks.push((id, Arc::new(Mutex::new(sink))));
+        id
+    }
+
+    // Returns false if the sink was already removed
+    #[allow(dead_code)]
+    pub fn remove_sink(&self, id: SinkId) -> bool {
+        let mut state = lock_or_recover(&self.state, LOG_TARGET, "remove_sink");
+        let before = state.sinks.len();
+        state.sinks.retain(|(sink, _)| *sink != id);
+        state.sinks.len() < before
+    }
+
+    // Sinks are called after the logger lock is released, so a sink may take
+    // locks whose holders log. Records logged by a sink itself only go to the
+    // ring buffer.
+    pub fn log(&self, level: Level, target: &str, message: &str) {
+        let (record, sinks) = {
+            let mut state = lock_or_recover(&self.state, LOG_TARGET, "log");
+            let max_level = state.target_levels.get(target).copied().unwrap_or(state.level);
+            if level > max_level {
+                return;
+            }
+
+            let record = LogRecord {
+                timestamp: self.start.elapsed(),
+                level,
+                target: target.to_string(),
+                pid: current_pid(),
+                message: message.to_string(),
+            };
+            if state.capacity > 0 {
+                if state.ring.len() == state.capacity {
+                    state.ring.pop_front();
+                }
+                state.ring.push_back(record.clone());
+            }
+            (record, state.sinks.clone())
+        };
+
+        if IN_SINK.with(|in_sink| in_sink.replace(true)) {
+            return;
+        }
+        let _in_sink = InSink;
+        for (_, sink) in sinks {
+            lock_or_recover(&sink, LOG_TARGET, "write").write(&record);
+        }
+    }
+
+    // The last `count` records kept in the ring buffer, oldest first
+    pub fn recent(&self, count: usize) -> Vec<LogRecord> {
+        let state = lock_or_recover(&self.state, LOG_TARGET, "recent");
+        let skip = state.ring.len().saturating_sub(count);
+        state.ring.iter().skip(skip).cloned().collect()
+    }
+}
+
+// Clears IN_SINK when the sinks are done, even if one of them panics
+struct InSink;
+
+impl Drop for InSink {
+    fn drop(&mut self) {
+        IN_SINK.with(|in_sink| in_sink.set(false));
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+thread_local! {
+    static CURRENT_PID: Cell<Option<u32>> = const { Cell::new(None) };
+    static IN_SINK: Cell<bool> = const { Cell::new(false) };
+}
+
+// The kernel-wide logger, writing to stderr until other sinks are added
+pub fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| {
+        let logger = Logger::new(DEFAULT_RING_CAPACITY);
+        logger.add_sink(Box::new(StderrSink));
+        logger
+    })
+}
+
+// Attributes records logged on this thread to `pid`; returns the previous value
+pub fn set_current_pid(pid: Option<u32>) -> Option<u32> {
+    CURRENT_PID.with(|current| current.replace(pid))
+}
+
+pub fn current_pid() -> Option<u32> {
+    CURRENT_PID.with(|current| current.get())
+}
+
+pub fn error(target: &str, message: &str) {
+    logger().log(Level::Error, target, message);
+}
+
+pub fn warn(target: &str, message: &str) {
+    logger().log(Level::Warn, target, message);
+}
+
+pub fn info(target: &str, message: &str) {
+    logger().log(Level::Info, target, message);
+}
+
+pub fn debug(target: &str, message: &str) {
+    logger().log(Level::Debug, target, message);
+}
+
+// Like `dmesg`: the last `count` kernel log lines
+#[allow(dead_code)]
+pub fn dmesg(count: usize) -> Vec<String> {
+    logger().recent(count).iter().map(LogRecord::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::TopicRegistry;
+
+    #[test]
+    fn test_ring_buffer_and_levels() {
+        let logger = Logger::new(3);
+        logger.set_target_level("scheduler", Level::Debug);
+
+        logger.log(Level::Info, "core_system", "one");
+        logger.log(Level::Debug, "core_system", "filtered out");
+        logger.log(Level::Debug, "scheduler", "two");
+        let previous = set_current_pid(Some(7));
+        logger.log(Level::Warn, "ipc", "three");
+        set_current_pid(previous);
+        logger.log(Level::Error, "hal", "four");
+
+        let records = logger.recent(10);
+        let messages: Vec<&str> = records.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, vec!["two", "three", "four"]);
+        assert_eq!(records[1].pid, Some(7));
+        assert_eq!(records[2].pid, None);
+        assert!(records[1].to_string().contains("WARN  ipc pid=7: three"));
+        assert_eq!(logger.recent(1)[0].message, "four");
+    }
+
+    #[test]
+    fn test_sinks() {
+        let file_system = Arc::new(Mutex::new(FileSystem::new()));
+        file_system.lock().unwrap().create_directory("/var").unwrap();
+        let ipc = crate::ipc::IPC::new();
+        ipc.create_mailbox(1);
+        let topics = TopicRegistry::new(ipc);
+        let collector = topics.subscribe::<LogRecord>("/rosout", 1).unwrap();
+
+        let logger = Logger::new(DEFAULT_RING_CAPACITY);
+        logger.add_sink(Box::new(FileSink::new(Arc::clone(&file_system), "/var/kernel.log")));
+        logger.add_sink(Box::new(TopicSink::new(topics.advertise("/rosout", 0, false).unwrap())));
+        logger.log(Level::Info, "hal", "motor started");
+        logger.log(Level::Error, "hal", "motor stalled");
+
+        let file_system = file_system.lock().unwrap();
+        let contents = String::from_utf8(file_system.read_file("/var/kernel.log").unwrap().clone()).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.lines().nth(1).unwrap().ends_with("ERROR hal: motor stalled"));
+        let (_, record) = collector.try_recv().unwrap().unwrap();
+        assert_eq!((record.level, record.message.as_str()), (Level::Info, "motor started"));
+    }
+}
@@ -1,5 +1,6 @@
-use tokio;
-use tokio::time::{interval, Duration};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
 
 mod core_system;
 mod input_processing;
@@ -9,85 +10,223 @@ mod ethical_evaluation;
 mod meta_learning_optimization;
 mod hal;
 mod scheduler;
+mod timer_wheel;
+mod indexed_heap;
+mod multicore;
+mod schedulability;
 mod ipc;
+mod codec;
+mod transport;
 mod memory_manager;
+mod shared_memory;
 mod file_system;
+mod logging;
+mod error;
+mod sync;
+mod supervisor;
+mod clock;
+mod topics;
+mod services;
+mod watchdog;
 
+use clock::{SharedClock, SimulatedClock};
 use core_system::CoreSystem;
-use hal::HAL;
+use hal::{Sensor, HAL};
 use scheduler::Scheduler;
 use ipc::IPC;
 use memory_manager::MemoryManager;
 use file_system::FileSystem;
+use topics::{Publisher, TopicRegistry};
+use watchdog::{Watchdog, WatchdogAction, WatchdogHandle};
+
+const LOOP_PERIOD: Duration = Duration::from_millis(100); // 10 Hz loop rate
 
 struct MetaROS {
     core_system: CoreSystem,
     hal: HAL,
     scheduler: Scheduler,
     ipc: IPC,
-    memory_manager: MemoryManager,
+    memory_manager: Arc<Mutex<MemoryManager>>,
     file_system: FileSystem,
+    watchdog: Watchdog,
+    main_loop_watchdog: WatchdogHandle,
+    clock: SharedClock,
+    topics: TopicRegistry,
+    distance_publisher: Publisher<f64>,
 }
 
 impl MetaROS {
     fn new() -> Self {
+        let ipc = IPC::new();
+        // Mailbox of the main loop; processes get theirs from the core system
+        ipc.create_mailbox(0);
+        let memory_manager = Arc::new(Mutex::new(MemoryManager::new(1024 * 1024))); // 1 MB of memory
+        // Expiries are reported to mailbox 0, which the main loop drains
+        let mut watchdog = Watchdog::new(ipc.clone(), 0);
+        // Five missed 10 Hz iterations stop the actuators
+        let main_loop_watchdog = watchdog.register("main_loop", None, Duration::from_millis(500), WatchdogAction::SafeStop);
+        let topics = TopicRegistry::new(ipc.clone());
+        // Latched, so consumers that start late still see the last reading
+        let distance_publisher = topics
+            .advertise("/sensors/distance", 0, true)
+            .expect("a fresh registry has no conflicting topics");
+        let mut core_system = CoreSystem::with_resources(ipc.clone(), Arc::clone(&memory_manager));
+        core_system.set_topics(topics.clone());
         MetaROS {
-            core_system: CoreSystem::new(),
+            core_system,
             hal: HAL::new(),
             scheduler: Scheduler::new(),
-            ipc: IPC::new(),
-            memory_manager: MemoryManager::new(1024 * 1024), // 1 MB of memory
+            ipc,
+            memory_manager,
             file_system: FileSystem::new(),
+            watchdog,
+            main_loop_watchdog,
+            clock: clock::system_clock(),
+            topics,
+            distance_publisher,
         }
     }
 
-    async fn run(&mut self) {
-        println!("MetaROS: Open Source Operating System for Ethical Robots");
+    // Runs the whole system on `clock`, e.g. a SimulatedClock for reproducible runs
+    fn set_clock(&mut self, clock: SharedClock) {
+        self.core_system.set_clock(clock.clone());
+        self.scheduler.set_clock(clock.clone());
+        self.ipc.set_clock(clock.clone());
+        self.watchdog.set_clock(clock.clone());
+        self.clock = clock;
+    }
+
+    async fn run_until<F: Future<Output = ()>>(&mut self, shutdown: F) {
+        logging::info("metaros", "MetaROS: Open Source Operating System for Ethical Robots");
         
-        let mut interval = interval(Duration::from_millis(100)); // 10 Hz loop rate
+        let mut next_tick = self.clock.now();
+        tokio::pin!(shutdown);
 
         loop {
-            interval.tick().await;
-
-            // Check for hardware events
-            if let Some(event) = self.hal.check_events() {
-                println!("Hardware event detected: {:?}", event);
-                // Handle the hardware event
+            tokio::select! {
+                _ = &mut shutdown => break,
+                // Messages are handled as they arrive rather than once per tick
+                Ok(message) = self.ipc.recv_async(0) => {
+                    self.handle_message(message);
+                    continue;
+                },
+                _ = self.clock.sleep_until(next_tick) => {},
             }
+            next_tick += LOOP_PERIOD;
 
-            // Process IPC messages
-            while let Some(message) = self.ipc.receive_message(0) { // 0 is a placeholder for the current process ID
-                println!("IPC message received: {:?}", message);
-                // Handle the IPC message
+            if !self.step().await {
+                break;
             }
+        }
 
-            // Manage memory
-            self.memory_manager.cleanup();
+        self.stop();
+    }
 
-            // Handle file system operations
-            // For demonstration, we'll just print the root directory contents
-            if let Ok(contents) = self.file_system.read_file("/") {
-                println!("Root directory contents: {:?}", contents);
-            }
+    // Runs `duration` of virtual time as fast as possible, advancing the
+    // simulated clock by one loop period per iteration
+    async fn simulate(&mut self, clock: SimulatedClock, duration: Duration) {
+        logging::info("metaros", &format!("Simulating {:?} of robot time", duration));
+        self.set_clock(Arc::new(clock.clone()));
+        while clock.elapsed() < duration && self.step().await {
+            clock.advance(LOOP_PERIOD);
+        }
+        self.stop();
+    }
 
-            // Schedule and run tasks
-            if let Some(task) = self.scheduler.get_next_task() {
-                println!("Running task: {:?}", task);
-                // Execute the task
-            }
+    fn stop(&mut self) {
+        for mailbox in self.ipc.stats() {
+            logging::debug("ipc", &format!(
+                "Mailbox {}: depth {}/{} (high {}), {} delivered, {} dropped, {} rejected",
+                mailbox.owner, mailbox.depth, mailbox.capacity, mailbox.high_watermark,
+                mailbox.delivered, mailbox.dropped, mailbox.rejected
+            ));
+        }
+        for topic in self.topics.topics() {
+            logging::debug("topics", &format!("Topic {} ({}): {} subscribers", topic.name, topic.type_name, topic.subscribers));
+        }
+        logging::info("scheduler", &format!("Scheduler statistics:\n{}", self.scheduler.stats()));
+        for exit in self.core_system.shutdown() {
+            logging::info("metaros", &format!("Process {} ({}) stopped with exit code {}", exit.pid, exit.name, exit.exit_code));
+        }
+    }
+
+    fn handle_message(&self, message: ipc::Message) {
+        logging::debug("ipc", &format!("IPC message received: {:?}", message));
+        // Handle the IPC message
+    }
+
+    // One iteration of the main loop; returns false when the system must halt
+    async fn step(&mut self) -> bool {
+        // Check liveness before kicking, so an overrun of the previous
+        // iteration is caught here
+        let expiries = self.watchdog.check(&mut self.core_system, &mut self.hal);
+        if expiries.iter().any(|expiry| expiry.action == WatchdogAction::Halt) {
+            return false;
+        }
+        self.main_loop_watchdog.kick();
 
-            // Allow the core system to perform its operations
-            self.core_system.run().await;
+        // Publish sensor readings
+        let distance = self.hal.distance_sensor.read();
+        if let Err(e) = self.distance_publisher.publish(&distance) {
+            logging::error("topics", &format!("Failed to publish distance: {}", e));
+        }
 
-            // Here you would integrate other components like input_processing, 
-            // decision_making, cultural_linguistic_analysis, ethical_evaluation, 
-            // and meta_learning_optimization as they are implemented.
+        // Check for hardware events
+        while let Some(event) = self.hal.check_events() {
+            logging::info("hal", &format!("Hardware event detected: {:?}", event));
+            // Handle the hardware event
         }
+
+        // Process IPC messages
+        while let Ok(Some(message)) = self.ipc.try_recv(0) { // 0 is a placeholder for the current process ID
+            self.handle_message(message);
+        }
+
+        // Report memory usage; freed blocks are coalesced as they are returned
+        {
+            let memory_manager = sync::lock_or_recover(&self.memory_manager, "memory_manager", "usage");
+            logging::debug("memory_manager", &format!("Memory in use: {}/{} bytes", memory_manager.used(), memory_manager.capacity()));
+        }
+
+        // Handle file system operations
+        // For demonstration, we'll just print the root directory contents
+        if let Ok(contents) = self.file_system.read_file("/") {
+            logging::debug("file_system", &format!("Root directory contents: {:?}", contents));
+        }
+
+        // Schedule and run tasks
+        while let Some(task) = self.scheduler.get_next_task() {
+            logging::debug("scheduler", &format!("Running task: {:?}", task));
+            if let Some(payload) = self.scheduler.take_payload(task.id) {
+                payload.run().await;
+            }
+            if let Err(e) = self.scheduler.complete_task(task.id) {
+                logging::error("scheduler", &format!("Scheduler error: {}", e));
+            }
+        }
+
+        // Allow the core system to run one scheduling quantum
+        if let Err(e) = self.core_system.step() {
+            logging::error("core_system", &format!("Core system error: {}", e));
+        }
+
+        // Here you would integrate other components like input_processing, 
+        // decision_making, cultural_linguistic_analysis, ethical_evaluation, 
+        // and meta_learning_optimization as they are implemented.
+
+        true
     }
 }
 
 #[tokio::main]
 async fn main() {
     let mut metaros = MetaROS::new();
-    metaros.run().await;
+    // METAROS_SIMULATE_SECS=<n> runs n seconds on a simulated clock instead of wall time
+    if let Some(seconds) = std::env::var("METAROS_SIMULATE_SECS").ok().and_then(|value| value.parse().ok()) {
+        metaros.simulate(SimulatedClock::new(), Duration::from_secs(seconds)).await;
+        return;
+    }
+    metaros.run_until(async {
+        let _ = tokio::signal::ctrl_c().await;
+    }).await;
 }
\ No newline at end of file
@@ -1,3 +1,30 @@
+use std::fmt;
+use std::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoryError {
+    OutOfMemory { requested: usize, largest_free: usize },
+    InvalidAddress(usize),
+    // An empty block would share its start address with the next allocation
+    ZeroSize,
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::OutOfMemory { requested, largest_free } => write!(
+                f,
+                "Cannot allocate {} bytes, largest free block is {} bytes",
+                requested, largest_free
+            ),
+            MemoryError::InvalidAddress(address) => write!(f, "No allocation at address {}", address),
+            MemoryError::ZeroSize => write!(f, "Cannot allocate 0 bytes"),
+        }
+    }
+}
+
+impl Error for MemoryError {}
+
 struct MemoryBlock {
     start: usize,
     size: usize,
@@ -17,35 +44,74 @@ impl MemoryManager {
         }
     }
 
-    pub fn allocate(&mut self, size: usize) -> Option<usize> {
+    pub fn allocate(&mut self, size: usize) -> Result<usize, MemoryError> {
+        if size == 0 {
+            return Err(MemoryError::ZeroSize);
+        }
         if let Some(index) = self.blocks.iter().position(|block| block.is_free && block.size >= size) {
-            let block = &mut self.blocks[index];
-            let alloc_start = block.start;
-            
-            if block.size > size {
+            let alloc_start = self.blocks[index].start;
+            let block_size = self.blocks[index].size;
+
+            if block_size > size {
                 let new_block = MemoryBlock {
                     start: alloc_start + size,
-                    size: block.size - size,
+                    size: block_size - size,
                     is_free: true,
                 };
                 self.blocks.insert(index + 1, new_block);
-                block.size = size;
+                self.blocks[index].size = size;
             }
-            
-            block.is_free = false;
-            Some(alloc_start)
+
+            self.blocks[index].is_free = false;
+            Ok(alloc_start)
         } else {
-            None
+            let largest_free = self.blocks.iter()
+                .filter(|block| block.is_free)
+                .map(|block| block.size)
+                .max()
+                .unwrap_or(0);
+            Err(MemoryError::OutOfMemory { requested: size, largest_free })
         }
     }
 
-    pub fn deallocate(&mut self, start: usize) {
-        if let Some(index) = self.blocks.iter().position(|block| block.start == start) {
-            self.blocks[index].is_free = true;
-            self.merge_free_blocks();
+    pub fn deallocate(&mut self, start: usize) -> Result<(), MemoryError> {
+        match self.blocks.iter().position(|block| block.start == start && !block.is_free) {
+            Some(index) => {
+                self.blocks[index].is_free = true;
+                self.merge_free_blocks();
+                Ok(())
+            },
+            None => Err(MemoryError::InvalidAddress(start)),
         }
     }
 
+    pub fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+
+    // Bytes currently handed out
+    pub fn used(&self) -> usize {
+        self.blocks.iter().filter(|block| !block.is_free).map(|block| block.size).sum()
+    }
+
+    // Contents of the allocation starting at `start`
+    pub fn bytes(&self, start: usize) -> Result<&[u8], MemoryError> {
+        let block = self.allocated_block(start)?;
+        Ok(&self.memory[block.start..block.start + block.size])
+    }
+
+    pub fn bytes_mut(&mut self, start: usize) -> Result<&mut [u8], MemoryError> {
+        let (start, size) = self.allocated_block(start).map(|block| (block.start, block.size))?;
+        Ok(&mut self.memory[start..start + size])
+    }
+
+    fn allocated_block(&self, start: usize) -> Result<&MemoryBlock, MemoryError> {
+        self.blocks
+            .iter()
+            .find(|block| block.start == start && !block.is_free)
+            .ok_or(MemoryError::InvalidAddress(start))
+    }
+
     fn merge_free_blocks(&mut self) {
         self.blocks.sort_by_key(|block| block.start);
         let mut i = 0;
@@ -67,6 +133,7 @@ mod tests {
     #[test]
     fn test_memory_manager() {
         let mut mm = MemoryManager::new(1024);
+        assert_eq!(mm.allocate(0), Err(MemoryError::ZeroSize));
 
         let addr1 = mm.allocate(100).unwrap();
         let addr2 = mm.allocate(200).unwrap();
@@ -76,15 +143,22 @@ mod tests {
         assert_eq!(addr2, 100);
         assert_eq!(addr3, 300);
 
-        mm.deallocate(addr2);
+        mm.deallocate(addr2).unwrap();
         let addr4 = mm.allocate(150).unwrap();
         assert_eq!(addr4, 100);
 
-        mm.deallocate(addr1);
-        mm.deallocate(addr3);
-        mm.deallocate(addr4);
+        mm.deallocate(addr1).unwrap();
+        mm.deallocate(addr3).unwrap();
+        mm.deallocate(addr4).unwrap();
+        assert_eq!(mm.deallocate(addr4), Err(MemoryError::InvalidAddress(addr4)));
+        assert_eq!(mm.bytes(addr4), Err(MemoryError::InvalidAddress(addr4)));
 
         let addr5 = mm.allocate(1000).unwrap();
         assert_eq!(addr5, 0);
+        mm.bytes_mut(addr5).unwrap()[999] = 7;
+        assert_eq!(mm.bytes(addr5).unwrap().len(), 1000);
+        assert_eq!(mm.bytes(addr5).unwrap()[999], 7);
+        assert_eq!(mm.allocate(100), Err(MemoryError::OutOfMemory { requested: 100, largest_free: 24 }));
+        assert_eq!((mm.used(), mm.capacity()), (1000, 1024));
     }
 }
\ No newline at end of file
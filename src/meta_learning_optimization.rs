@@ -3,11 +3,13 @@ pub struct MetaLearningOptimizer {
 }
 
 impl MetaLearningOptimizer {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         // TODO: Initialize meta-learning optimization components
         MetaLearningOptimizer {}
     }
 
+    #[allow(dead_code)]
     pub async fn optimize(&self) {
         // TODO: Implement meta-learning optimization logic
         println!("Optimizing system performance...");
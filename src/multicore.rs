@@ -0,0 +1,371 @@
+use std::cell::Cell;
+use std::cmp::Ordering as CmpOrdering;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::indexed_heap::IndexedHeap;
+use crate::logging;
+use crate::scheduler::{Priority, Task};
+use crate::sync::{lock_or_recover, RecoveredGuard};
+
+const LOG_TARGET: &str = "multicore";
+
+// Upper bound on how long an idle worker sleeps before looking for work again
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+
+// A CpuSet is a 64-bit mask
+pub const MAX_CORES: usize = 64;
+
+// Set of logical cores a task or process may run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuSet(pub u64);
+
+impl CpuSet {
+    pub fn all() -> Self {
+        CpuSet(u64::MAX)
+    }
+
+    #[allow(dead_code)]
+    pub fn single(core: usize) -> Result<Self, MultiCoreError> {
+        Self::from_cores(&[core])
+    }
+
+    pub fn from_cores(cores: &[usize]) -> Result<Self, MultiCoreError> {
+        let mut mask = 0;
+        for &core in cores {
+            if core >= MAX_CORES {
+                return Err(MultiCoreError::InvalidCore(core));
+            }
+            mask |= 1 << core;
+        }
+        Ok(CpuSet(mask))
+    }
+
+    pub fn contains(&self, core: usize) -> bool {
+        core < MAX_CORES && self.0 & (1 << core) != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MultiCoreError {
+    // The mask selects none of the available cores
+    InvalidAffinity(CpuSet),
+    InvalidCore(usize),
+    AlreadyStarted,
+}
+
+impl fmt::Display for MultiCoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultiCoreError::InvalidAffinity(mask) => write!(f, "Affinity mask {:#x} selects no available core", mask.0),
+            MultiCoreError::InvalidCore(core) => write!(f, "No core: {}", core),
+            MultiCoreError::AlreadyStarted => write!(f, "Workers are already running"),
+        }
+    }
+}
+
+impl Error for MultiCoreError {}
+
+struct QueuedTask {
+    task: Task,
+    affinity: CpuSet,
+    work: Box<dyn FnOnce() + Send>,
+}
+
+impl Ord for QueuedTask {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.task.cmp(&other.task)
+    }
+}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.task == other.task
+    }
+}
+
+impl Eq for QueuedTask {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreStats {
+    pub core: usize,
+    pub isolated: bool,
+    pub queued: usize,
+    pub executed: u64,
+    // Tasks this core took from other cores' queues
+    pub stolen: u64,
+}
+
+struct Core {
+    queue: Mutex<IndexedHeap<QueuedTask>>,
+    isolated: AtomicBool,
+    executed: AtomicU64,
+    stolen: AtomicU64,
+}
+
+struct Shared {
+    cores: Vec<Core>,
+    shutdown: AtomicBool,
+    idle: Mutex<()>,
+    work_available: Condvar,
+}
+
+impl Shared {
+    fn queue(&self, core: usize) -> RecoveredGuard<'_, IndexedHeap<QueuedTask>> {
+        lock_or_recover(&self.cores[core].queue, LOG_TARGET, "run queue")
+    }
+
+    fn is_isolated(&self, core: usize) -> bool {
+        self.cores[core].isolated.load(Ordering::SeqCst)
+    }
+
+    // Takes the best task from `victim`'s queue that may run on `thief`
+    fn steal(&self, thief: usize, victim: usize) -> Option<QueuedTask> {
+        let mut queue = self.queue(victim);
+        let mut best: Option<(u32, &QueuedTask)> = None;
+        for (id, queued) in queue.iter() {
+            if queued.affinity.contains(thief) && best.is_none_or(|(_, current)| queued < current) {
+                best = Some((id, queued));
+            }
+        }
+        let id = best.map(|(id, _)| id)?;
+        queue.remove(id)
+    }
+
+    fn next_task(&self, core: usize) -> Option<QueuedTask> {
+        if let Some((_, queued)) = self.queue(core).pop() {
+            return Some(queued);
+        }
+        // Isolated cores only run what was placed on them and are never stolen from
+        if self.is_isolated(core) {
+            return None;
+        }
+        let count = self.cores.len();
+        for offset in 1..count {
+            let victim = (core + offset) % count;
+            if self.is_isolated(victim) {
+                continue;
+            }
+            if let Some(queued) = self.steal(core, victim) {
+                self.cores[core].stolen.fetch_add(1, Ordering::SeqCst);
+                return Some(queued);
+            }
+        }
+        None
+    }
+}
+
+thread_local! {
+    static CURRENT_CORE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// The worker core the calling thread belongs to, if any
+#[allow(dead_code)]
+pub fn current_core() -> Option<usize> {
+    CURRENT_CORE.with(|core| core.get())
+}
+
+// Runs closures on one OS thread per logical core. Each core has its own run
+// queue ordered like the Scheduler's; idle cores steal work they are allowed
+// to run from busy ones, except for isolated cores, which are reserved for
+// tasks pinned to them (e.g. real-time control loops).
+pub struct MultiCoreScheduler {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    next_task_id: AtomicU32,
+}
+
+impl MultiCoreScheduler {
+    #[allow(dead_code)]
+    pub fn new(core_count: usize) -> Self {
+        let cores = (0..core_count.clamp(1, MAX_CORES))
+            .map(|_| Core {
+                queue: Mutex::new(IndexedHeap::new()),
+                isolated: AtomicBool::new(false),
+                executed: AtomicU64::new(0),
+                stolen: AtomicU64::new(0),
+            })
+            .collect();
+        MultiCoreScheduler {
+            shared: Arc::new(Shared {
+                cores,
+                shutdown: AtomicBool::new(false),
+                idle: Mutex::new(()),
+                work_available: Condvar::new(),
+            }),
+            workers: Vec::new(),
+            next_task_id: AtomicU32::new(1),
+        }
+    }
+
+    pub fn core_count(&self) -> usize {
+        self.shared.cores.len()
+    }
+
+    // Reserves `core` for tasks whose affinity selects only isolated cores
+    #[allow(dead_code)]
+    pub fn isolate_core(&mut self, core: usize) -> Result<(), MultiCoreError> {
+        let state = self.shared.cores.get(core).ok_or(MultiCoreError::InvalidCore(core))?;
+        state.isolated.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Spawns one worker thread per core
+    #[allow(dead_code)]
+    pub fn start(&mut self) -> Result<(), MultiCoreError> {
+        if !self.workers.is_empty() {
+            return Err(MultiCoreError::AlreadyStarted);
+        }
+        for core in 0..self.core_count() {
+            let shared = Arc::clone(&self.shared);
+            let worker = thread::Builder::new()
+                .name(format!("metaros-core-{}", core))
+                .spawn(move || worker_loop(shared, core))
+                .expect("failed to spawn worker thread");
+            self.workers.push(worker);
+        }
+        logging::info(LOG_TARGET, &format!("Started {} worker cores", self.core_count()));
+        Ok(())
+    }
+
+    // Queues `work` on the least loaded core allowed by `affinity`. Isolated
+    // cores are only used when the mask selects no other core.
+    #[allow(dead_code)]
+    pub fn submit<F>(&self, priority: u8, deadline: Duration, affinity: CpuSet, work: F) -> Result<u32, MultiCoreError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let allowed: Vec<usize> = (0..self.core_count()).filter(|&core| affinity.contains(core)).collect();
+        let shared_cores: Vec<usize> = allowed.iter().copied().filter(|&core| !self.shared.is_isolated(core)).collect();
+        let candidates = if shared_cores.is_empty() { allowed } else { shared_cores };
+        let core = candidates
+            .into_iter()
+            .min_by_key(|&core| self.shared.queue(core).len())
+            .ok_or(MultiCoreError::InvalidAffinity(affinity))?;
+
+        let id = self.next_task_id.fetch_add(1, Ordering::SeqCst);
+        let now = Instant::now();
+        let task = Task { id, priority: Priority(priority), released_at: now, deadline: now + deadline, recurring_id: None };
+        self.shared.queue(core).push(id, QueuedTask { task, affinity, work: Box::new(work) });
+        self.shared.work_available.notify_all();
+        Ok(id)
+    }
+
+    #[allow(dead_code)]
+    pub fn stats(&self) -> Vec<CoreStats> {
+        self.shared
+            .cores
+            .iter()
+            .enumerate()
+            .map(|(index, core)| CoreStats {
+                core: index,
+                isolated: core.isolated.load(Ordering::SeqCst),
+                queued: self.shared.queue(index).len(),
+                executed: core.executed.load(Ordering::SeqCst),
+                stolen: core.stolen.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    // Lets the workers drain their queues, then joins them
+    pub fn shutdown(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        self.shared.work_available.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                logging::error(LOG_TARGET, "Worker thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for MultiCoreScheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn worker_loop(shared: Arc<Shared>, core: usize) {
+    CURRENT_CORE.with(|current| current.set(Some(core)));
+    loop {
+        if let Some(queued) = shared.next_task(core) {
+            let id = queued.task.id;
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(queued.work)).is_err() {
+                logging::error(LOG_TARGET, &format!("Task {} panicked on core {}", id, core));
+            }
+            shared.cores[core].executed.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+        if shared.shutdown.load(Ordering::SeqCst) {
+            return;
+        }
+        // Nothing is guarded by `idle`, so a poisoned lock needs no recovery
+        let idle = shared.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = shared.work_available.wait_timeout(idle, IDLE_WAIT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affinity_and_isolation() {
+        let mut scheduler = MultiCoreScheduler::new(3);
+        scheduler.isolate_core(2).unwrap();
+        assert_eq!(scheduler.isolate_core(3), Err(MultiCoreError::InvalidCore(3)));
+        assert_eq!(
+            scheduler.submit(1, Duration::ZERO, CpuSet::single(5).unwrap(), || {}),
+            Err(MultiCoreError::InvalidAffinity(CpuSet::single(5).unwrap()))
+        );
+        assert_eq!(CpuSet::from_cores(&[1, 64]), Err(MultiCoreError::InvalidCore(64)));
+        scheduler.start().unwrap();
+
+        let ran_on = Arc::new(Mutex::new(Vec::new()));
+        for index in 0..20 {
+            let ran_on = Arc::clone(&ran_on);
+            let affinity = if index % 2 == 0 { CpuSet::single(1).unwrap() } else { CpuSet::all() };
+            scheduler.submit(1, Duration::from_secs(1), affinity, move || {
+                ran_on.lock().unwrap().push((affinity, current_core().unwrap()));
+            }).unwrap();
+        }
+        let control_core = Arc::new(Mutex::new(None));
+        let recorded = Arc::clone(&control_core);
+        scheduler.submit(9, Duration::from_millis(10), CpuSet::single(2).unwrap(), move || {
+            *recorded.lock().unwrap() = current_core();
+        }).unwrap();
+        scheduler.shutdown();
+
+        let ran_on = ran_on.lock().unwrap();
+        assert_eq!(ran_on.len(), 20);
+        assert!(ran_on.iter().all(|(affinity, core)| affinity.contains(*core) && *core != 2));
+        assert_eq!(*control_core.lock().unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_work_stealing() {
+        let mut scheduler = MultiCoreScheduler::new(2);
+        // Core 0 gets the long task and every other short one
+        scheduler.submit(5, Duration::ZERO, CpuSet::all(), || thread::sleep(Duration::from_millis(100))).unwrap();
+        for _ in 0..10 {
+            scheduler.submit(1, Duration::from_secs(1), CpuSet::all(), || {}).unwrap();
+        }
+        assert_eq!(scheduler.stats()[0].queued, 6);
+        scheduler.start().unwrap();
+        scheduler.shutdown();
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.iter().map(|core| core.executed).sum::<u64>(), 11);
+        assert!(stats[1].stolen > 0);
+        assert!(stats[1].executed > 5);
+    }
+}
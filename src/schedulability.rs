@@ -0,0 +1,194 @@
+use std::fmt;
+use std::time::Duration;
+use crate::scheduler::Priority;
+
+// Timing parameters of a periodic task, or of a sporadic task with its
+// minimum inter-arrival time as period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskParams {
+    pub priority: Priority,
+    pub wcet: Duration,
+    pub period: Duration,
+    pub deadline: Duration,
+}
+
+impl TaskParams {
+    // Deadlines longer than the period are clamped to it, which keeps every
+    // test sound at the cost of some pessimism
+    fn effective_deadline(&self) -> Duration {
+        self.deadline.min(self.period)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdmissionTest {
+    // Accept every task
+    None,
+    // Liu & Layland utilization bound, sufficient for fixed priorities
+    #[allow(dead_code)]
+    RateMonotonic,
+    // Exact response-time analysis for fixed priorities
+    #[allow(dead_code)]
+    ResponseTime,
+    // Density test, sufficient for earliest deadline first
+    #[allow(dead_code)]
+    EarliestDeadlineFirst,
+}
+
+impl fmt::Display for AdmissionTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AdmissionTest::None => "none",
+            AdmissionTest::RateMonotonic => "rate-monotonic bound",
+            AdmissionTest::ResponseTime => "response-time analysis",
+            AdmissionTest::EarliestDeadlineFirst => "EDF density",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulabilityReport {
+    pub utilization: f64,
+    pub density: f64,
+    pub rm_bound: f64,
+    pub rm_schedulable: bool,
+    // Worst-case response time per task in input order, None if it can exceed the deadline
+    pub response_times: Vec<Option<Duration>>,
+    pub rta_schedulable: bool,
+    pub edf_schedulable: bool,
+}
+
+impl SchedulabilityReport {
+    pub fn passes(&self, test: AdmissionTest) -> bool {
+        match test {
+            AdmissionTest::None => true,
+            AdmissionTest::RateMonotonic => self.rm_schedulable,
+            AdmissionTest::ResponseTime => self.rta_schedulable,
+            AdmissionTest::EarliestDeadlineFirst => self.edf_schedulable,
+        }
+    }
+}
+
+fn ratio(a: Duration, b: Duration) -> f64 {
+    if b.is_zero() {
+        return f64::INFINITY;
+    }
+    a.as_secs_f64() / b.as_secs_f64()
+}
+
+// Sum of wcet / period
+pub fn utilization(tasks: &[TaskParams]) -> f64 {
+    tasks.iter().map(|task| ratio(task.wcet, task.period)).sum()
+}
+
+// Sum of wcet / min(deadline, period)
+pub fn density(tasks: &[TaskParams]) -> f64 {
+    tasks.iter().map(|task| ratio(task.wcet, task.effective_deadline())).sum()
+}
+
+// n(2^(1/n) - 1), approaching ln 2 for large n
+pub fn rm_utilization_bound(task_count: usize) -> f64 {
+    if task_count == 0 {
+        return 1.0;
+    }
+    let n = task_count as f64;
+    n * (2f64.powf(1.0 / n) - 1.0)
+}
+
+// Sufficient test. The bound assumes implicit deadlines, so shorter deadlines
+// are accounted for by using the density instead of the utilization.
+pub fn rate_monotonic_test(tasks: &[TaskParams]) -> bool {
+    density(tasks) <= rm_utilization_bound(tasks.len())
+}
+
+// Worst-case response time of each task under fixed priorities, where a
+// higher `Priority` preempts a lower one and equal priorities are assumed to
+// delay each other. None means the deadline can be missed.
+pub fn response_time_analysis(tasks: &[TaskParams]) -> Vec<Option<Duration>> {
+    tasks
+        .iter()
+        .enumerate()
+        .map(|(index, task)| {
+            let interfering: Vec<&TaskParams> = tasks
+                .iter()
+                .enumerate()
+                .filter(|&(other, candidate)| other != index && candidate.priority >= task.priority)
+                .map(|(_, candidate)| candidate)
+                .collect();
+            let deadline = task.effective_deadline().as_nanos();
+            let mut response = task.wcet.as_nanos();
+            loop {
+                if response > deadline {
+                    return None;
+                }
+                let mut next = task.wcet.as_nanos();
+                for other in interfering.iter() {
+                    let period = other.period.as_nanos().max(1);
+                    next += response.div_ceil(period) * other.wcet.as_nanos();
+                }
+                if next == response {
+                    return Some(Duration::from_nanos(response as u64));
+                }
+                response = next;
+            }
+        })
+        .collect()
+}
+
+// Sufficient for EDF with constrained deadlines, exact for implicit ones
+pub fn edf_density_test(tasks: &[TaskParams]) -> bool {
+    density(tasks) <= 1.0
+}
+
+pub fn analyze(tasks: &[TaskParams]) -> SchedulabilityReport {
+    let response_times = response_time_analysis(tasks);
+    SchedulabilityReport {
+        utilization: utilization(tasks),
+        density: density(tasks),
+        rm_bound: rm_utilization_bound(tasks.len()),
+        rm_schedulable: rate_monotonic_test(tasks),
+        rta_schedulable: response_times.iter().all(Option::is_some),
+        response_times,
+        edf_schedulable: edf_density_test(tasks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(priority: u8, wcet_ms: u64, period_ms: u64) -> TaskParams {
+        TaskParams {
+            priority: Priority(priority),
+            wcet: Duration::from_millis(wcet_ms),
+            period: Duration::from_millis(period_ms),
+            deadline: Duration::from_millis(period_ms),
+        }
+    }
+
+    #[test]
+    fn test_schedulability() {
+        assert!((rm_utilization_bound(1) - 1.0).abs() < 1e-9);
+        assert!((rm_utilization_bound(3) - 0.7798).abs() < 1e-4);
+
+        // U = 0.25 + 0.25 + 0.3 = 0.8: above the RM bound but schedulable
+        let tasks = [task(3, 1, 4), task(2, 2, 8), task(1, 6, 20)];
+        let report = analyze(&tasks);
+        assert!((report.utilization - 0.8).abs() < 1e-9);
+        assert!(!report.rm_schedulable);
+        assert_eq!(report.response_times[0], Some(Duration::from_millis(1)));
+        assert_eq!(report.response_times[1], Some(Duration::from_millis(3)));
+        assert_eq!(report.response_times[2], Some(Duration::from_millis(14)));
+        assert!(report.rta_schedulable);
+        assert!(report.edf_schedulable);
+
+        // U = 1.1 fails every test
+        let overloaded = [task(2, 3, 5), task(1, 5, 10)];
+        let report = analyze(&overloaded);
+        assert_eq!(report.response_times[1], None);
+        assert!(!report.passes(AdmissionTest::ResponseTime));
+        assert!(!report.passes(AdmissionTest::EarliestDeadlineFirst));
+        assert!(report.passes(AdmissionTest::None));
+    }
+}
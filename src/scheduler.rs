@@ -1,20 +1,154 @@
-use std::collections::BinaryHeap;
-use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::future::Future;
+use std::pin::Pin;
 use std::time::{Duration, Instant};
+use std::fmt;
+use std::error::Error;
+use crate::clock::{self, SharedClock};
+use crate::indexed_heap::IndexedHeap;
+use crate::logging;
+use crate::schedulability::{self, AdmissionTest, SchedulabilityReport, TaskParams};
+use crate::timer_wheel::TimerWheel;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+const LOG_TARGET: &str = "scheduler";
+
+// Completed task timings kept for `task_timing`
+const TIMING_HISTORY: usize = 256;
+
+// 1 ms release resolution over a 1 s revolution
+const TIMER_RESOLUTION: Duration = Duration::from_millis(1);
+const TIMER_SLOTS: usize = 1024;
+
+// Upper bounds of the response-time histogram buckets; the last bucket is unbounded
+pub const RESPONSE_BUCKETS: [Duration; 9] = [
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchedulerError {
+    TaskNotFound(u32),
+    // A periodic task needs a period and a sporadic one a minimum
+    // inter-arrival time greater than zero
+    ZeroPeriod,
+    // Admitting the task would make the task set fail the admission test
+    Unschedulable { test: AdmissionTest, utilization: f64, density: f64 },
+    MutexNotFound(u32),
+    NotMutexOwner { mutex: u32, task: u32 },
+    // Blocking `task` on `mutex` would close a cycle of waiting tasks
+    Deadlock { mutex: u32, task: u32 },
+    // The task's priority is above the mutex's priority ceiling
+    CeilingViolation { mutex: u32, task: u32 },
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchedulerError::TaskNotFound(id) => write!(f, "No task with id: {}", id),
+            SchedulerError::ZeroPeriod => write!(f, "Recurring tasks need a period greater than zero"),
+            SchedulerError::Unschedulable { test, utilization, density } => write!(
+                f,
+                "Task set fails the {} test (utilization {:.3}, density {:.3})",
+                test, utilization, density
+            ),
+            SchedulerError::MutexNotFound(id) => write!(f, "No mutex with id: {}", id),
+            SchedulerError::NotMutexOwner { mutex, task } => write!(f, "Task {} does not own mutex {}", task, mutex),
+            SchedulerError::Deadlock { mutex, task } => write!(f, "Task {} locking mutex {} would deadlock", task, mutex),
+            SchedulerError::CeilingViolation { mutex, task } => {
+                write!(f, "Task {} has a higher priority than the ceiling of mutex {}", task, mutex)
+            },
+        }
+    }
+}
+
+impl Error for SchedulerError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Priority(pub u8);
 
+#[derive(Debug, Clone)]
 pub struct Task {
     pub id: u32,
     pub priority: Priority,
+    pub released_at: Instant,
     pub deadline: Instant,
+    // The periodic or sporadic task this job was released for
+    pub recurring_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recurrence {
+    // Released every `period`, starting `phase` after it is added
+    Periodic { period: Duration, phase: Duration },
+    // Released on `trigger_sporadic`, at most once per `min_interarrival`
+    Sporadic { min_interarrival: Duration },
+}
+
+// A task that is released repeatedly, each release becoming a one-shot job
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurringTask {
+    pub id: u32,
+    pub priority: Priority,
+    pub recurrence: Recurrence,
+    pub relative_deadline: Duration,
+    pub last_release: Option<Instant>,
+    // A sporadic release is waiting in the timer wheel
+    pub release_pending: bool,
+    // Worst-case execution time, declared for admission control
+    pub wcet: Option<Duration>,
+}
+
+impl RecurringTask {
+    fn params(&self) -> Option<TaskParams> {
+        let period = match self.recurrence {
+            Recurrence::Periodic { period, .. } => period,
+            Recurrence::Sporadic { min_interarrival } => min_interarrival,
+        };
+        Some(TaskParams {
+            priority: self.priority,
+            wcet: self.wcet?,
+            period,
+            deadline: self.relative_deadline,
+        })
+    }
+}
+
+// A zero period would release jobs in an endless loop
+fn check_period(recurrence: Recurrence) -> Result<(), SchedulerError> {
+    match recurrence {
+        Recurrence::Periodic { period, .. } if period.is_zero() => Err(SchedulerError::ZeroPeriod),
+        Recurrence::Sporadic { min_interarrival } if min_interarrival.is_zero() => Err(SchedulerError::ZeroPeriod),
+        _ => Ok(()),
+    }
+}
+
+// Higher priorities come first
+fn priority_order(a: Priority, b: Priority) -> Ordering {
+    b.cmp(&a)
+}
+
+// Earlier deadlines come first; work without a deadline goes last
+fn deadline_order(a: Option<Instant>, b: Option<Instant>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
 }
 
 impl Ord for Task {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.priority.cmp(&self.priority)
-            .then_with(|| self.deadline.cmp(&other.deadline))
+    fn cmp(&self, other: &Self) -> Ordering {
+        priority_order(self.priority, other.priority)
+            .then_with(|| deadline_order(Some(self.deadline), Some(other.deadline)))
     }
 }
 
@@ -32,35 +166,951 @@ impl PartialEq for Task {
 
 impl Eq for Task {}
 
+// A runnable entity offered to a scheduling policy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub id: u32,
+    pub priority: Priority,
+    pub deadline: Option<Instant>,
+}
+
+// Decides which of the ready candidates runs next. Candidates are passed in
+// queue order, so picking the first of several equals gives round-robin.
+#[allow(dead_code)]
+pub trait SchedulingPolicy: Send {
+    fn name(&self) -> &'static str;
+    fn select(&mut self, candidates: &[Candidate]) -> Option<usize>;
+}
+
+// Index of the first candidate that is not ordered after any other
+fn first_by<F>(candidates: &[Candidate], order: F) -> Option<usize>
+where
+    F: Fn(&Candidate, &Candidate) -> Ordering,
+{
+    let mut best: Option<usize> = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        match best {
+            Some(b) if order(candidate, &candidates[b]) != Ordering::Less => {},
+            _ => best = Some(index),
+        }
+    }
+    best
+}
+
+pub struct RoundRobin;
+
+impl SchedulingPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn select(&mut self, candidates: &[Candidate]) -> Option<usize> {
+        if candidates.is_empty() { None } else { Some(0) }
+    }
+}
+
+// Highest priority first, round-robin within a priority level
+pub struct FixedPriority;
+
+impl SchedulingPolicy for FixedPriority {
+    fn name(&self) -> &'static str {
+        "fixed-priority"
+    }
+
+    fn select(&mut self, candidates: &[Candidate]) -> Option<usize> {
+        first_by(candidates, |a, b| priority_order(a.priority, b.priority))
+    }
+}
+
+// Earliest deadline first, falling back to the Scheduler's priority ordering
+pub struct EarliestDeadlineFirst;
+
+impl SchedulingPolicy for EarliestDeadlineFirst {
+    fn name(&self) -> &'static str {
+        "earliest-deadline-first"
+    }
+
+    fn select(&mut self, candidates: &[Candidate]) -> Option<usize> {
+        first_by(candidates, |a, b| {
+            deadline_order(a.deadline, b.deadline)
+                .then_with(|| priority_order(a.priority, b.priority))
+        })
+    }
+}
+
+// Release, start and completion times of one task
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskTiming {
+    pub id: u32,
+    pub recurring_id: Option<u32>,
+    pub priority: Priority,
+    pub released_at: Instant,
+    pub deadline: Instant,
+    pub started_at: Option<Instant>,
+    pub completed_at: Option<Instant>,
+}
+
+impl TaskTiming {
+    // Time from release to completion
+    pub fn response_time(&self) -> Option<Duration> {
+        self.completed_at.map(|completed| completed.duration_since(self.released_at))
+    }
+
+    // Time from release until the task was handed out
+    pub fn start_latency(&self) -> Option<Duration> {
+        self.started_at.map(|started| started.duration_since(self.released_at))
+    }
+
+    pub fn missed_deadline(&self) -> bool {
+        self.completed_at.is_some_and(|completed| completed > self.deadline)
+    }
+
+    fn task(&self) -> Task {
+        Task {
+            id: self.id,
+            priority: self.priority,
+            released_at: self.released_at,
+            deadline: self.deadline,
+            recurring_id: self.recurring_id,
+        }
+    }
+}
+
+// Reported once per task: when it is handed out after its deadline, or else
+// when it completes after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadlineMiss {
+    pub task_id: u32,
+    pub deadline: Instant,
+    pub detected_at: Instant,
+    pub lateness: Duration,
+}
+
+// Aggregated timing of completed tasks
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingStats {
+    pub completed: u64,
+    pub deadline_misses: u64,
+    pub min_response: Option<Duration>,
+    pub max_response: Option<Duration>,
+    pub total_response: Duration,
+    pub min_start_latency: Option<Duration>,
+    pub max_start_latency: Option<Duration>,
+    // Counts per `RESPONSE_BUCKETS` bucket plus one overflow bucket
+    pub response_histogram: [u64; RESPONSE_BUCKETS.len() + 1],
+}
+
+impl TimingStats {
+    pub fn new() -> Self {
+        TimingStats {
+            completed: 0,
+            deadline_misses: 0,
+            min_response: None,
+            max_response: None,
+            total_response: Duration::ZERO,
+            min_start_latency: None,
+            max_start_latency: None,
+            response_histogram: [0; RESPONSE_BUCKETS.len() + 1],
+        }
+    }
+
+    pub fn record(&mut self, timing: &TaskTiming) {
+        let (Some(response), Some(latency)) = (timing.response_time(), timing.start_latency()) else {
+            return;
+        };
+        self.completed += 1;
+        if timing.missed_deadline() {
+            self.deadline_misses += 1;
+        }
+        self.min_response = Some(self.min_response.map_or(response, |min| min.min(response)));
+        self.max_response = Some(self.max_response.map_or(response, |max| max.max(response)));
+        self.total_response += response;
+        self.min_start_latency = Some(self.min_start_latency.map_or(latency, |min| min.min(latency)));
+        self.max_start_latency = Some(self.max_start_latency.map_or(latency, |max| max.max(latency)));
+        let bucket = RESPONSE_BUCKETS.iter().position(|&bound| response <= bound).unwrap_or(RESPONSE_BUCKETS.len());
+        self.response_histogram[bucket] += 1;
+    }
+
+    pub fn mean_response(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        Some(self.total_response / self.completed as u32)
+    }
+
+    // Spread between the fastest and slowest response
+    pub fn response_jitter(&self) -> Option<Duration> {
+        Some(self.max_response? - self.min_response?)
+    }
+
+    // Spread of the delay between release and start
+    pub fn start_jitter(&self) -> Option<Duration> {
+        Some(self.max_start_latency? - self.min_start_latency?)
+    }
+}
+
+impl fmt::Display for TimingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "completed: {}, deadline misses: {}", self.completed, self.deadline_misses)?;
+        writeln!(
+            f,
+            "response min/mean/max: {:?}/{:?}/{:?}, jitter: {:?}, start jitter: {:?}",
+            self.min_response.unwrap_or_default(),
+            self.mean_response().unwrap_or_default(),
+            self.max_response.unwrap_or_default(),
+            self.response_jitter().unwrap_or_default(),
+            self.start_jitter().unwrap_or_default()
+        )?;
+        for (index, count) in self.response_histogram.iter().enumerate() {
+            match RESPONSE_BUCKETS.get(index) {
+                Some(bound) => writeln!(f, "  <= {:>8?}: {}", bound, count)?,
+                None => writeln!(f, "   > {:>8?}: {}", RESPONSE_BUCKETS[index - 1], count)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulerStats {
+    pub pending: usize,
+    pub running: usize,
+    pub timing: TimingStats,
+}
+
+impl fmt::Display for SchedulerStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "pending: {}, running: {}", self.pending, self.running)?;
+        write!(f, "{}", self.timing)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockProtocol {
+    // The owner inherits the highest priority of the tasks it blocks
+    #[allow(dead_code)]
+    Inheritance,
+    // Immediate priority ceiling: the owner runs at the ceiling while it holds the lock
+    #[allow(dead_code)]
+    Ceiling(Priority),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockOutcome {
+    Acquired,
+    // The task was taken off the CPU until the lock is handed to it
+    Blocked,
+}
+
+struct KernelMutex {
+    protocol: LockProtocol,
+    owner: Option<u32>,
+    waiters: Vec<u32>,
+}
+
+pub type DeadlineMissHandler = Box<dyn FnMut(&DeadlineMiss) + Send>;
+
+// The work a task performs when it is handed out
+pub enum TaskPayload {
+    Closure(Box<dyn FnOnce() + Send>),
+    Future(Pin<Box<dyn Future<Output = ()> + Send>>),
+}
+
+impl TaskPayload {
+    #[allow(dead_code)]
+    pub fn closure<F: FnOnce() + Send + 'static>(work: F) -> Self {
+        TaskPayload::Closure(Box::new(work))
+    }
+
+    #[allow(dead_code)]
+    pub fn future<F: Future<Output = ()> + Send + 'static>(work: F) -> Self {
+        TaskPayload::Future(Box::pin(work))
+    }
+
+    pub async fn run(self) {
+        match self {
+            TaskPayload::Closure(work) => work(),
+            TaskPayload::Future(work) => work.await,
+        }
+    }
+}
+
+// A task held back until all of its predecessors completed
+struct WaitingTask {
+    task: Task,
+    remaining: HashSet<u32>,
+}
+
 pub struct Scheduler {
-    tasks: BinaryHeap<Reverse<Task>>,
+    // Ready tasks keyed by id; the minimum runs first
+    tasks: IndexedHeap<Task>,
     next_task_id: u32,
+    running: HashMap<u32, TaskTiming>,
+    // Tasks that started but were preempted or are blocked on a mutex
+    suspended: HashMap<u32, TaskTiming>,
+    history: VecDeque<TaskTiming>,
+    stats: TimingStats,
+    on_deadline_miss: Option<DeadlineMissHandler>,
+    // Started tasks whose deadline miss was already reported at dispatch
+    late: HashSet<u32>,
+    recurring: HashMap<u32, RecurringTask>,
+    recurring_stats: HashMap<u32, TimingStats>,
+    releases: TimerWheel<u32>,
+    admission_test: AdmissionTest,
+    mutexes: HashMap<u32, KernelMutex>,
+    next_mutex_id: u32,
+    blocked_on: HashMap<u32, u32>,
+    payloads: HashMap<u32, TaskPayload>,
+    waiting: HashMap<u32, WaitingTask>,
+    dependents: HashMap<u32, Vec<u32>>,
+    clock: SharedClock,
 }
 
 impl Scheduler {
     pub fn new() -> Self {
+        let clock = clock::system_clock();
         Scheduler {
-            tasks: BinaryHeap::new(),
+            tasks: IndexedHeap::new(),
             next_task_id: 1,
+            running: HashMap::new(),
+            suspended: HashMap::new(),
+            history: VecDeque::with_capacity(TIMING_HISTORY),
+            stats: TimingStats::new(),
+            on_deadline_miss: None,
+            late: HashSet::new(),
+            recurring: HashMap::new(),
+            recurring_stats: HashMap::new(),
+            releases: TimerWheel::new(TIMER_RESOLUTION, TIMER_SLOTS, clock.now()),
+            admission_test: AdmissionTest::None,
+            mutexes: HashMap::new(),
+            next_mutex_id: 1,
+            blocked_on: HashMap::new(),
+            payloads: HashMap::new(),
+            waiting: HashMap::new(),
+            dependents: HashMap::new(),
+            clock,
         }
     }
 
-    pub fn add_task(&mut self, priority: u8, deadline: Duration) -> u32 {
-        let task_id = self.next_task_id;
+    // Replaces the time source, e.g. with a SimulatedClock. Pending releases
+    // keep their instants, so switch clocks before adding recurring tasks.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        let mut releases = TimerWheel::new(TIMER_RESOLUTION, TIMER_SLOTS, clock.now());
+        for (at, id) in self.releases.drain() {
+            releases.schedule(at, id);
+        }
+        self.releases = releases;
+        self.clock = clock;
+    }
+
+    // Test run by `admit_periodic_task` and `admit_sporadic_task`
+    #[allow(dead_code)]
+    pub fn set_admission_test(&mut self, test: AdmissionTest) {
+        self.admission_test = test;
+    }
+
+    // Called whenever a task starts or completes after its deadline
+    #[allow(dead_code)]
+    pub fn set_deadline_miss_handler(&mut self, handler: DeadlineMissHandler) {
+        self.on_deadline_miss = Some(handler);
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_task_id;
         self.next_task_id += 1;
+        id
+    }
 
+    pub fn add_task(&mut self, priority: u8, deadline: Duration) -> u32 {
+        let task_id = self.next_id();
+        let now = self.clock.now();
         let task = Task {
             id: task_id,
             priority: Priority(priority),
-            deadline: Instant::now() + deadline,
+            released_at: now,
+            deadline: now + deadline,
+            recurring_id: None,
         };
 
-        self.tasks.push(Reverse(task));
+        self.tasks.push(task_id, task);
         task_id
     }
 
+    // Adds a task carrying `payload` that is only queued once every task in
+    // `dependencies` has completed. Dependencies must already exist, so the
+    // tasks always form a DAG. A dependency that is no longer active counts as
+    // completed: `cancel` only cascades to dependents added before it.
+    #[allow(dead_code)]
+    pub fn add_task_with(
+        &mut self,
+        priority: u8,
+        deadline: Duration,
+        payload: Option<TaskPayload>,
+        dependencies: &[u32],
+    ) -> Result<u32, SchedulerError> {
+        let mut remaining = HashSet::new();
+        for &dependency in dependencies {
+            if dependency >= self.next_task_id || self.recurring.contains_key(&dependency) {
+                return Err(SchedulerError::TaskNotFound(dependency));
+            }
+            // Anything else that is no longer queued, started or waiting has completed
+            let active = self.tasks.contains_key(dependency)
+                || self.running.contains_key(&dependency)
+                || self.suspended.contains_key(&dependency)
+                || self.waiting.contains_key(&dependency);
+            if active {
+                remaining.insert(dependency);
+            }
+        }
+
+        let task_id = self.add_task(priority, deadline);
+        if let Some(payload) = payload {
+            self.payloads.insert(task_id, payload);
+        }
+        if !remaining.is_empty() {
+            let task = self.tasks.remove(task_id).ok_or(SchedulerError::TaskNotFound(task_id))?;
+            for &dependency in remaining.iter() {
+                self.dependents.entry(dependency).or_default().push(task_id);
+            }
+            self.waiting.insert(task_id, WaitingTask { task, remaining });
+        }
+        Ok(task_id)
+    }
+
+    // The payload of a handed-out task; the caller runs it before `complete_task`
+    pub fn take_payload(&mut self, task_id: u32) -> Option<TaskPayload> {
+        self.payloads.remove(&task_id)
+    }
+
+    // Tasks waiting for predecessors, in id order
+    #[allow(dead_code)]
+    pub fn waiting(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.waiting.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    // Queues the dependents of `task_id` whose last predecessor it was
+    fn release_dependents(&mut self, task_id: u32) {
+        for dependent in self.dependents.remove(&task_id).unwrap_or_default() {
+            let ready = match self.waiting.get_mut(&dependent) {
+                Some(waiting) => {
+                    waiting.remaining.remove(&task_id);
+                    waiting.remaining.is_empty()
+                },
+                None => false,
+            };
+            if !ready {
+                continue;
+            }
+            if let Some(WaitingTask { mut task, .. }) = self.waiting.remove(&dependent) {
+                task.released_at = self.clock.now();
+                self.tasks.push(dependent, task);
+            }
+        }
+    }
+
+    // Declares a task released every `period`, the first time `phase` from now.
+    // Each release is a job with a deadline `relative_deadline` after its release.
+    #[allow(dead_code)]
+    pub fn add_periodic_task(
+        &mut self,
+        priority: u8,
+        period: Duration,
+        phase: Duration,
+        relative_deadline: Duration,
+    ) -> Result<u32, SchedulerError> {
+        self.insert_recurring(priority, Recurrence::Periodic { period, phase }, relative_deadline, None)
+    }
+
+    // Declares a task that is released by `trigger_sporadic`
+    #[allow(dead_code)]
+    pub fn add_sporadic_task(&mut self, priority: u8, min_interarrival: Duration, relative_deadline: Duration) -> Result<u32, SchedulerError> {
+        self.insert_recurring(priority, Recurrence::Sporadic { min_interarrival }, relative_deadline, None)
+    }
+
+    fn insert_recurring(
+        &mut self,
+        priority: u8,
+        recurrence: Recurrence,
+        relative_deadline: Duration,
+        wcet: Option<Duration>,
+    ) -> Result<u32, SchedulerError> {
+        check_period(recurrence)?;
+        let id = self.next_id();
+        let release_pending = match recurrence {
+            Recurrence::Periodic { phase, .. } => {
+                self.releases.schedule(self.clock.now() + phase, id);
+                true
+            },
+            Recurrence::Sporadic { .. } => false,
+        };
+        self.recurring.insert(id, RecurringTask {
+            id,
+            priority: Priority(priority),
+            recurrence,
+            relative_deadline,
+            last_release: None,
+            release_pending,
+            wcet,
+        });
+        Ok(id)
+    }
+
+    // Like `add_periodic_task`, but rejects the task if the resulting task set
+    // fails the admission test
+    #[allow(dead_code)]
+    pub fn admit_periodic_task(
+        &mut self,
+        priority: u8,
+        period: Duration,
+        phase: Duration,
+        relative_deadline: Duration,
+        wcet: Duration,
+    ) -> Result<u32, SchedulerError> {
+        let recurrence = Recurrence::Periodic { period, phase };
+        check_period(recurrence)?;
+        self.check_admission(TaskParams { priority: Priority(priority), wcet, period, deadline: relative_deadline })?;
+        self.insert_recurring(priority, recurrence, relative_deadline, Some(wcet))
+    }
+
+    #[allow(dead_code)]
+    pub fn admit_sporadic_task(
+        &mut self,
+        priority: u8,
+        min_interarrival: Duration,
+        relative_deadline: Duration,
+        wcet: Duration,
+    ) -> Result<u32, SchedulerError> {
+        let recurrence = Recurrence::Sporadic { min_interarrival };
+        check_period(recurrence)?;
+        self.check_admission(TaskParams { priority: Priority(priority), wcet, period: min_interarrival, deadline: relative_deadline })?;
+        self.insert_recurring(priority, recurrence, relative_deadline, Some(wcet))
+    }
+
+    fn check_admission(&self, candidate: TaskParams) -> Result<(), SchedulerError> {
+        let mut tasks = self.admitted_params();
+        tasks.push(candidate);
+        let report = schedulability::analyze(&tasks);
+        if report.passes(self.admission_test) {
+            return Ok(());
+        }
+        logging::warn(LOG_TARGET, &format!(
+            "Rejected task with wcet {:?} and period {:?}: {} test failed",
+            candidate.wcet, candidate.period, self.admission_test
+        ));
+        Err(SchedulerError::Unschedulable {
+            test: self.admission_test,
+            utilization: report.utilization,
+            density: report.density,
+        })
+    }
+
+    // Timing parameters of the recurring tasks that declared a wcet, in id order
+    fn admitted_params(&self) -> Vec<TaskParams> {
+        let mut tasks: Vec<&RecurringTask> = self.recurring.values().collect();
+        tasks.sort_by_key(|task| task.id);
+        tasks.iter().filter_map(|task| task.params()).collect()
+    }
+
+    // Schedulability of the currently admitted periodic and sporadic tasks
+    #[allow(dead_code)]
+    pub fn schedulability(&self) -> SchedulabilityReport {
+        schedulability::analyze(&self.admitted_params())
+    }
+
+    // Requests a release of a sporadic task. A trigger that comes earlier than
+    // the minimum inter-arrival time is deferred; returns the release time.
+    #[allow(dead_code)]
+    pub fn trigger_sporadic(&mut self, id: u32) -> Result<Instant, SchedulerError> {
+        self.trigger_sporadic_at(id, self.clock.now())
+    }
+
+    fn trigger_sporadic_at(&mut self, id: u32, now: Instant) -> Result<Instant, SchedulerError> {
+        let task = self.recurring.get_mut(&id).ok_or(SchedulerError::TaskNotFound(id))?;
+        let Recurrence::Sporadic { min_interarrival } = task.recurrence else {
+            return Err(SchedulerError::TaskNotFound(id));
+        };
+        let earliest = task.last_release.map_or(now, |last| (last + min_interarrival).max(now));
+        if !task.release_pending {
+            task.release_pending = true;
+            self.releases.schedule(earliest, id);
+        }
+        Ok(earliest)
+    }
+
+    #[allow(dead_code)]
+    pub fn remove_recurring_task(&mut self, id: u32) -> Result<RecurringTask, SchedulerError> {
+        let task = self.recurring.remove(&id).ok_or(SchedulerError::TaskNotFound(id))?;
+        self.recurring_stats.remove(&id);
+        self.releases.retain(|&key| key != id);
+        Ok(task)
+    }
+
+    #[allow(dead_code)]
+    pub fn recurring_task(&self, id: u32) -> Option<&RecurringTask> {
+        self.recurring.get(&id)
+    }
+
+    // When the next periodic or sporadic job is due
+    #[allow(dead_code)]
+    pub fn next_release(&self) -> Option<Instant> {
+        self.releases.next_expiry()
+    }
+
+    // Queues a job for every periodic or sporadic task whose release time has
+    // come; returns how many were released. `get_next_task` calls this itself.
+    pub fn release_due(&mut self) -> usize {
+        self.release_due_at(self.clock.now())
+    }
+
+    fn release_due_at(&mut self, now: Instant) -> usize {
+        let mut released = 0;
+        // Releasing a periodic job schedules the next one, which may be due as well
+        loop {
+            let due = self.releases.advance(now);
+            if due.is_empty() {
+                return released;
+            }
+            for (release, id) in due {
+                let Some(recurring) = self.recurring.get_mut(&id) else {
+                    continue;
+                };
+                recurring.last_release = Some(release);
+                recurring.release_pending = false;
+                if let Recurrence::Periodic { period, .. } = recurring.recurrence {
+                    recurring.release_pending = true;
+                    self.releases.schedule(release + period, id);
+                }
+                let (priority, deadline) = (recurring.priority, release + recurring.relative_deadline);
+                let job = Task {
+                    id: self.next_id(),
+                    priority,
+                    released_at: release,
+                    deadline,
+                    recurring_id: Some(id),
+                };
+                self.tasks.push(job.id, job);
+                released += 1;
+            }
+        }
+    }
+
+    // Hands out the next task and records its start time; report the end
+    // with `complete_task`
     pub fn get_next_task(&mut self) -> Option<Task> {
-        self.tasks.pop().map(|Reverse(task)| task)
+        self.release_due();
+        let (_, task) = self.tasks.pop()?;
+        let now = self.clock.now();
+        // A resumed task keeps its first start time and base priority
+        let timing = self.suspended.remove(&task.id).unwrap_or(TaskTiming {
+            id: task.id,
+            recurring_id: task.recurring_id,
+            priority: task.priority,
+            released_at: task.released_at,
+            deadline: task.deadline,
+            started_at: Some(now),
+            completed_at: None,
+        });
+        if now > timing.deadline && self.late.insert(task.id) {
+            self.report_deadline_miss(task.id, timing.deadline, now);
+        }
+        self.running.insert(task.id, timing);
+        Some(task)
+    }
+
+    fn report_deadline_miss(&mut self, task_id: u32, deadline: Instant, detected_at: Instant) {
+        let miss = DeadlineMiss { task_id, deadline, detected_at, lateness: detected_at.duration_since(deadline) };
+        logging::warn(LOG_TARGET, &format!("Task {} missed its deadline by {:?}", task_id, miss.lateness));
+        if let Some(handler) = self.on_deadline_miss.as_mut() {
+            handler(&miss);
+        }
+    }
+
+    // Puts a running task back into the ready queue, e.g. when a higher
+    // priority task was released
+    #[allow(dead_code)]
+    pub fn preempt(&mut self, task_id: u32) -> Result<(), SchedulerError> {
+        let timing = self.running.remove(&task_id).ok_or(SchedulerError::TaskNotFound(task_id))?;
+        self.suspended.insert(task_id, timing);
+        self.make_ready(task_id);
+        Ok(())
+    }
+
+    fn make_ready(&mut self, task_id: u32) {
+        if let (Some(timing), Some(priority)) = (self.suspended.get(&task_id), self.effective_priority(task_id)) {
+            self.tasks.push(task_id, Task { priority, ..timing.task() });
+        }
+    }
+
+    pub fn create_mutex(&mut self, protocol: LockProtocol) -> u32 {
+        let id = self.next_mutex_id;
+        self.next_mutex_id += 1;
+        self.mutexes.insert(id, KernelMutex { protocol, owner: None, waiters: Vec::new() });
+        id
+    }
+
+    // Locks `mutex_id` for the running task `task_id`. If another task owns
+    // it, the caller is blocked and the owner inherits its priority; the lock
+    // is handed over on `unlock`, after which `lock` returns Acquired.
+    pub fn lock(&mut self, mutex_id: u32, task_id: u32) -> Result<LockOutcome, SchedulerError> {
+        let mutex = self.mutexes.get(&mutex_id).ok_or(SchedulerError::MutexNotFound(mutex_id))?;
+        if mutex.owner == Some(task_id) {
+            return Ok(LockOutcome::Acquired);
+        }
+        let base = self.running.get(&task_id).ok_or(SchedulerError::TaskNotFound(task_id))?.priority;
+        if let LockProtocol::Ceiling(ceiling) = mutex.protocol {
+            if base > ceiling {
+                return Err(SchedulerError::CeilingViolation { mutex: mutex_id, task: task_id });
+            }
+        }
+
+        let Some(owner) = mutex.owner else {
+            if let Some(mutex) = self.mutexes.get_mut(&mutex_id) {
+                mutex.owner = Some(task_id);
+            }
+            self.refresh_priorities();
+            return Ok(LockOutcome::Acquired);
+        };
+
+        // Follow the chain of owners that are themselves blocked
+        let mut holder = Some(owner);
+        while let Some(current) = holder {
+            if current == task_id {
+                return Err(SchedulerError::Deadlock { mutex: mutex_id, task: task_id });
+            }
+            holder = self.blocked_on.get(&current).and_then(|m| self.mutexes.get(m)).and_then(|m| m.owner);
+        }
+
+        if let Some(mutex) = self.mutexes.get_mut(&mutex_id) {
+            mutex.waiters.push(task_id);
+        }
+        self.blocked_on.insert(task_id, mutex_id);
+        if let Some(timing) = self.running.remove(&task_id) {
+            self.suspended.insert(task_id, timing);
+        }
+        self.refresh_priorities();
+        logging::debug(LOG_TARGET, &format!(
+            "Task {} blocked on mutex {} owned by task {}, which now runs at {:?}",
+            task_id, mutex_id, owner, self.effective_priority(owner)
+        ));
+        Ok(LockOutcome::Blocked)
+    }
+
+    // Releases `mutex_id` and hands it to the highest-priority waiter, which
+    // becomes ready again. Returns the new owner.
+    pub fn unlock(&mut self, mutex_id: u32, task_id: u32) -> Result<Option<u32>, SchedulerError> {
+        let mutex = self.mutexes.get(&mutex_id).ok_or(SchedulerError::MutexNotFound(mutex_id))?;
+        if mutex.owner != Some(task_id) {
+            return Err(SchedulerError::NotMutexOwner { mutex: mutex_id, task: task_id });
+        }
+
+        // Highest effective priority first, FIFO among equals
+        let mut next: Option<(usize, Priority)> = None;
+        for (index, &waiter) in mutex.waiters.iter().enumerate() {
+            let priority = self.effective_priority(waiter).unwrap_or(Priority(0));
+            if next.is_none_or(|(_, best)| priority > best) {
+                next = Some((index, priority));
+            }
+        }
+
+        let mut new_owner = None;
+        if let Some(mutex) = self.mutexes.get_mut(&mutex_id) {
+            new_owner = next.map(|(index, _)| mutex.waiters.remove(index));
+            mutex.owner = new_owner;
+        }
+        if let Some(waiter) = new_owner {
+            self.blocked_on.remove(&waiter);
+            self.make_ready(waiter);
+        }
+        self.refresh_priorities();
+        Ok(new_owner)
+    }
+
+    // The priority a task currently runs at: its own, raised by the mutexes it holds
+    pub fn effective_priority(&self, task_id: u32) -> Option<Priority> {
+        let base = self
+            .running
+            .get(&task_id)
+            .or_else(|| self.suspended.get(&task_id))
+            .map(|timing| timing.priority)?;
+        let mut priority = base;
+        for mutex in self.mutexes.values().filter(|mutex| mutex.owner == Some(task_id)) {
+            if let LockProtocol::Ceiling(ceiling) = mutex.protocol {
+                priority = priority.max(ceiling);
+            }
+            // Waiters never own a mutex their owner waits on, so this terminates
+            for &waiter in mutex.waiters.iter() {
+                if let Some(inherited) = self.effective_priority(waiter) {
+                    priority = priority.max(inherited);
+                }
+            }
+        }
+        Some(priority)
+    }
+
+    // Re-orders the ready tasks whose effective priority changed
+    fn refresh_priorities(&mut self) {
+        for id in self.tasks.keys() {
+            if let Some(priority) = self.effective_priority(id) {
+                self.tasks.update(id, |task| task.priority = priority);
+            }
+        }
+    }
+
+    // Removes a task whether it is queued, running, blocked on a mutex or
+    // waiting for predecessors. Mutexes it holds are handed to their next
+    // waiter. Tasks that depend on it can never run and are cancelled as well.
+    #[allow(dead_code)]
+    pub fn cancel(&mut self, task_id: u32) -> Result<Task, SchedulerError> {
+        let task = if let Some(task) = self.tasks.remove(task_id) {
+            self.suspended.remove(&task_id);
+            task
+        } else if let Some(waiting) = self.waiting.remove(&task_id) {
+            for predecessor in waiting.remaining {
+                if let Some(dependents) = self.dependents.get_mut(&predecessor) {
+                    dependents.retain(|&dependent| dependent != task_id);
+                }
+            }
+            waiting.task
+        } else {
+            let timing = self.running.remove(&task_id).or_else(|| self.suspended.remove(&task_id));
+            timing.ok_or(SchedulerError::TaskNotFound(task_id))?.task()
+        };
+
+        if let Some(mutex_id) = self.blocked_on.remove(&task_id) {
+            if let Some(mutex) = self.mutexes.get_mut(&mutex_id) {
+                mutex.waiters.retain(|&waiter| waiter != task_id);
+            }
+        }
+        let mut held: Vec<u32> = self
+            .mutexes
+            .iter()
+            .filter(|(_, mutex)| mutex.owner == Some(task_id))
+            .map(|(&id, _)| id)
+            .collect();
+        held.sort();
+        for mutex_id in held {
+            if let Ok(Some(owner)) = self.unlock(mutex_id, task_id) {
+                logging::warn(LOG_TARGET, &format!("Mutex {} handed to task {} because task {} was cancelled", mutex_id, owner, task_id));
+            }
+        }
+        // Owners of a mutex the task was waiting for no longer inherit from it
+        self.refresh_priorities();
+
+        self.late.remove(&task_id);
+        self.payloads.remove(&task_id);
+        for dependent in self.dependents.remove(&task_id).unwrap_or_default() {
+            if self.cancel(dependent).is_ok() {
+                logging::warn(LOG_TARGET, &format!("Cancelled task {} because task {} was cancelled", dependent, task_id));
+            }
+        }
+        Ok(task)
+    }
+
+    // Changes the base priority of a task in any state: queued, running,
+    // preempted, blocked on a mutex or waiting for predecessors
+    #[allow(dead_code)]
+    pub fn update_priority(&mut self, task_id: u32, priority: u8) -> Result<(), SchedulerError> {
+        let priority = Priority(priority);
+        if let Some(waiting) = self.waiting.get_mut(&task_id) {
+            waiting.task.priority = priority;
+            return Ok(());
+        }
+        let timing = self.running.get_mut(&task_id).or_else(|| self.suspended.get_mut(&task_id));
+        match timing {
+            Some(timing) => timing.priority = priority,
+            None if self.tasks.update(task_id, |task| task.priority = priority) => return Ok(()),
+            None => return Err(SchedulerError::TaskNotFound(task_id)),
+        }
+        if let Some(effective) = self.effective_priority(task_id) {
+            self.tasks.update(task_id, |task| task.priority = effective);
+        }
+        // Tasks blocked on mutexes this task owns may inherit less or more now
+        if self.blocked_on.contains_key(&task_id) {
+            self.refresh_priorities();
+        }
+        Ok(())
+    }
+
+    // Moves the deadline of a task in any state to `deadline` from now
+    #[allow(dead_code)]
+    pub fn update_deadline(&mut self, task_id: u32, deadline: Duration) -> Result<(), SchedulerError> {
+        let deadline = self.clock.now() + deadline;
+        if let Some(waiting) = self.waiting.get_mut(&task_id) {
+            waiting.task.deadline = deadline;
+            return Ok(());
+        }
+        let queued = self.tasks.update(task_id, |task| task.deadline = deadline);
+        let timing = self.running.get_mut(&task_id).or_else(|| self.suspended.get_mut(&task_id));
+        match timing {
+            Some(timing) => timing.deadline = deadline,
+            None if queued => {},
+            None => return Err(SchedulerError::TaskNotFound(task_id)),
+        }
+        Ok(())
+    }
+
+    // The task `get_next_task` would hand out, ignoring releases not yet queued
+    #[allow(dead_code)]
+    pub fn peek(&self) -> Option<&Task> {
+        self.tasks.peek().map(|(_, task)| task)
+    }
+
+    // Ready tasks in the order they would run
+    #[allow(dead_code)]
+    pub fn pending(&self) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self.tasks.iter().map(|(_, task)| task.clone()).collect();
+        tasks.sort();
+        tasks
+    }
+
+    pub fn complete_task(&mut self, task_id: u32) -> Result<TaskTiming, SchedulerError> {
+        let mut timing = self.running.remove(&task_id).ok_or(SchedulerError::TaskNotFound(task_id))?;
+        let completed_at = self.clock.now();
+        timing.completed_at = Some(completed_at);
+        self.stats.record(&timing);
+        if let Some(recurring_id) = timing.recurring_id {
+            if self.recurring.contains_key(&recurring_id) {
+                self.recurring_stats.entry(recurring_id).or_insert_with(TimingStats::new).record(&timing);
+            }
+        }
+
+        if !self.late.remove(&task_id) && timing.missed_deadline() {
+            self.report_deadline_miss(task_id, timing.deadline, completed_at);
+        }
+
+        if self.history.len() == TIMING_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(timing.clone());
+        self.payloads.remove(&task_id);
+        self.release_dependents(task_id);
+        Ok(timing)
+    }
+
+    // Timing of a started or recently completed task
+    #[allow(dead_code)]
+    pub fn task_timing(&self, task_id: u32) -> Option<TaskTiming> {
+        self.running
+            .get(&task_id)
+            .or_else(|| self.suspended.get(&task_id))
+            .or_else(|| self.history.iter().rev().find(|timing| timing.id == task_id))
+            .cloned()
+    }
+
+    // Timing of all completed jobs of one periodic or sporadic task
+    #[allow(dead_code)]
+    pub fn recurring_stats(&self, id: u32) -> Option<&TimingStats> {
+        self.recurring_stats.get(&id)
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        SchedulerStats {
+            pending: self.tasks.len(),
+            running: self.running.len(),
+            timing: self.stats.clone(),
+        }
     }
 }
 
@@ -81,4 +1131,288 @@ mod tests {
         assert_eq!(scheduler.get_next_task().unwrap().id, task1);
         assert!(scheduler.get_next_task().is_none());
     }
+
+    #[test]
+    fn test_scheduling_policies() {
+        let now = Instant::now();
+        let candidates = [
+            Candidate { id: 1, priority: Priority(1), deadline: None },
+            Candidate { id: 2, priority: Priority(3), deadline: Some(now + Duration::from_secs(5)) },
+            Candidate { id: 3, priority: Priority(3), deadline: Some(now + Duration::from_secs(1)) },
+            Candidate { id: 4, priority: Priority(2), deadline: Some(now + Duration::from_secs(1)) },
+        ];
+
+        assert_eq!(RoundRobin.select(&candidates), Some(0));
+        assert_eq!(FixedPriority.select(&candidates), Some(1));
+        assert_eq!(EarliestDeadlineFirst.select(&candidates), Some(2));
+        assert_eq!(EarliestDeadlineFirst.select(&candidates[..1]), Some(0));
+        assert_eq!(FixedPriority.select(&[]), None);
+    }
+
+    #[test]
+    fn test_timing_stats_and_deadline_misses() {
+        let mut scheduler = Scheduler::new();
+        let misses = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = std::sync::Arc::clone(&misses);
+        scheduler.set_deadline_miss_handler(Box::new(move |miss| recorded.lock().unwrap().push(miss.task_id)));
+
+        let on_time = scheduler.add_task(2, Duration::from_secs(10));
+        let late = scheduler.add_task(1, Duration::ZERO);
+        assert_eq!(scheduler.get_next_task().unwrap().id, on_time);
+        let timing = scheduler.complete_task(on_time).unwrap();
+        assert!(!timing.missed_deadline());
+        assert!(timing.response_time().unwrap() >= timing.start_latency().unwrap());
+
+        assert_eq!(scheduler.get_next_task().unwrap().id, late);
+        assert_eq!(scheduler.stats().running, 1);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(scheduler.complete_task(late).unwrap().missed_deadline());
+        assert_eq!(*misses.lock().unwrap(), vec![late]);
+        assert_eq!(scheduler.complete_task(late), Err(SchedulerError::TaskNotFound(late)));
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.timing.completed, 2);
+        assert_eq!(stats.timing.deadline_misses, 1);
+        assert_eq!(stats.timing.response_histogram.iter().sum::<u64>(), 2);
+        assert!(stats.timing.response_jitter().is_some());
+        assert!(stats.to_string().contains("deadline misses: 1"));
+        assert!(scheduler.task_timing(late).unwrap().completed_at.is_some());
+    }
+
+    #[test]
+    fn test_deadline_miss_at_dispatch() {
+        let clock = crate::clock::SimulatedClock::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(std::sync::Arc::new(clock.clone()));
+        let misses = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = std::sync::Arc::clone(&misses);
+        scheduler.set_deadline_miss_handler(Box::new(move |miss: &DeadlineMiss| recorded.lock().unwrap().push(miss.clone())));
+
+        // A task that is still queued when its deadline passes is reported as
+        // soon as it is handed out, not only once it completes
+        let starved = scheduler.add_task(1, Duration::from_millis(5));
+        clock.advance(Duration::from_millis(8));
+        assert_eq!(scheduler.get_next_task().unwrap().id, starved);
+        assert_eq!(misses.lock().unwrap()[0].lateness, Duration::from_millis(3));
+
+        clock.advance(Duration::from_millis(2));
+        assert!(scheduler.complete_task(starved).unwrap().missed_deadline());
+        assert_eq!(misses.lock().unwrap().len(), 1);
+        assert_eq!(scheduler.stats().timing.deadline_misses, 1);
+    }
+
+    #[test]
+    fn test_periodic_and_sporadic_tasks() {
+        let mut scheduler = Scheduler::new();
+        let start = Instant::now();
+        let motor = scheduler.add_periodic_task(3, Duration::from_millis(10), Duration::ZERO, Duration::from_millis(10)).unwrap();
+        let telemetry = scheduler.add_periodic_task(1, Duration::from_secs(1), Duration::from_millis(500), Duration::from_secs(1)).unwrap();
+        let estop = scheduler.add_sporadic_task(5, Duration::from_millis(50), Duration::from_millis(5)).unwrap();
+        assert_eq!(scheduler.add_periodic_task(1, Duration::ZERO, Duration::ZERO, Duration::from_secs(1)), Err(SchedulerError::ZeroPeriod));
+        assert_eq!(scheduler.add_sporadic_task(1, Duration::ZERO, Duration::from_secs(1)), Err(SchedulerError::ZeroPeriod));
+        let ms = Duration::from_millis;
+        assert_eq!(scheduler.admit_periodic_task(1, Duration::ZERO, Duration::ZERO, ms(1), ms(1)), Err(SchedulerError::ZeroPeriod));
+
+        // Motor releases at 0, 10, 20 and 30 ms; telemetry is not due yet
+        assert_eq!(scheduler.release_due_at(start + Duration::from_millis(35)), 4);
+        let (_, job) = scheduler.tasks.pop().unwrap();
+        assert_eq!(job.recurring_id, Some(motor));
+        assert_eq!(job.deadline, job.released_at + Duration::from_millis(10));
+        assert_eq!(scheduler.release_due_at(start + Duration::from_millis(36)), 0);
+
+        // A second trigger within the inter-arrival time is deferred
+        let now = start + Duration::from_millis(40);
+        assert_eq!(scheduler.trigger_sporadic_at(estop, now), Ok(now));
+        scheduler.release_due_at(now);
+        let deferred = scheduler.trigger_sporadic_at(estop, now + Duration::from_millis(1)).unwrap();
+        assert_eq!(deferred, now + Duration::from_millis(50));
+        assert_eq!(scheduler.trigger_sporadic_at(telemetry, now), Err(SchedulerError::TaskNotFound(telemetry)));
+
+        // The release at 40 ms plus the already queued ones; the sporadic job comes first
+        let next = scheduler.get_next_task().unwrap();
+        assert_eq!(next.recurring_id, Some(estop));
+        scheduler.complete_task(next.id).unwrap();
+        assert_eq!(scheduler.recurring_stats(estop).unwrap().completed, 1);
+
+        scheduler.remove_recurring_task(motor).unwrap();
+        assert!(scheduler.recurring_task(motor).is_none());
+        assert!(scheduler.next_release().is_some());
+    }
+
+    #[test]
+    fn test_admission_control() {
+        let mut scheduler = Scheduler::new();
+        scheduler.set_admission_test(AdmissionTest::ResponseTime);
+        let ms = Duration::from_millis;
+
+        scheduler.admit_periodic_task(3, ms(10), Duration::ZERO, ms(10), ms(4)).unwrap();
+        scheduler.admit_sporadic_task(2, ms(20), ms(20), ms(6)).unwrap();
+        let rejected = scheduler.admit_periodic_task(1, ms(40), Duration::ZERO, ms(40), ms(20));
+        assert!(matches!(rejected, Err(SchedulerError::Unschedulable { test: AdmissionTest::ResponseTime, .. })));
+        assert_eq!(scheduler.schedulability().response_times, vec![Some(ms(4)), Some(ms(10))]);
+
+        // The same task passes the EDF test, which allows full utilization
+        scheduler.set_admission_test(AdmissionTest::EarliestDeadlineFirst);
+        assert!(scheduler.admit_periodic_task(1, ms(40), Duration::ZERO, ms(40), ms(12)).is_ok());
+        assert!(!scheduler.schedulability().rm_schedulable);
+    }
+
+    #[test]
+    fn test_priority_inheritance() {
+        let mut scheduler = Scheduler::new();
+        let resource = scheduler.create_mutex(LockProtocol::Inheritance);
+
+        // The low priority task takes the lock and is preempted by the high one
+        let low = scheduler.add_task(1, Duration::from_secs(3));
+        assert_eq!(scheduler.get_next_task().unwrap().id, low);
+        assert_eq!(scheduler.lock(resource, low), Ok(LockOutcome::Acquired));
+        let high = scheduler.add_task(3, Duration::from_secs(1));
+        scheduler.preempt(low).unwrap();
+        assert_eq!(scheduler.get_next_task().unwrap().id, high);
+        assert_eq!(scheduler.lock(resource, high), Ok(LockOutcome::Blocked));
+        assert_eq!(scheduler.effective_priority(low), Some(Priority(3)));
+
+        // Without inheritance the medium task would run now and delay the high one
+        let medium = scheduler.add_task(2, Duration::from_secs(2));
+        assert_eq!(scheduler.get_next_task().unwrap().id, low);
+        assert_eq!(scheduler.unlock(resource, low), Ok(Some(high)));
+        assert_eq!(scheduler.effective_priority(low), Some(Priority(1)));
+        scheduler.preempt(low).unwrap();
+        assert_eq!(scheduler.get_next_task().unwrap().id, high);
+        assert_eq!(scheduler.lock(resource, high), Ok(LockOutcome::Acquired));
+        assert_eq!(scheduler.unlock(resource, medium), Err(SchedulerError::NotMutexOwner { mutex: resource, task: medium }));
+        assert_eq!(scheduler.unlock(resource, high), Ok(None));
+        scheduler.complete_task(high).unwrap();
+        assert_eq!(scheduler.get_next_task().unwrap().id, medium);
+        assert_eq!(scheduler.get_next_task().unwrap().id, low);
+    }
+
+    #[test]
+    fn test_priority_ceiling_and_deadlock() {
+        let mut scheduler = Scheduler::new();
+        let ceiling = scheduler.create_mutex(LockProtocol::Ceiling(Priority(2)));
+        let other = scheduler.create_mutex(LockProtocol::Inheritance);
+        let a = scheduler.add_task(2, Duration::from_secs(1));
+        let b = scheduler.add_task(1, Duration::from_secs(1));
+        let urgent = scheduler.add_task(5, Duration::from_secs(1));
+
+        assert_eq!(scheduler.get_next_task().unwrap().id, urgent);
+        assert_eq!(scheduler.lock(ceiling, urgent), Err(SchedulerError::CeilingViolation { mutex: ceiling, task: urgent }));
+
+        assert_eq!(scheduler.get_next_task().unwrap().id, a);
+        assert_eq!(scheduler.get_next_task().unwrap().id, b);
+        scheduler.lock(ceiling, b).unwrap();
+        assert_eq!(scheduler.effective_priority(b), Some(Priority(2)));
+        scheduler.lock(other, a).unwrap();
+        assert_eq!(scheduler.lock(ceiling, a), Ok(LockOutcome::Blocked));
+        assert_eq!(scheduler.lock(other, b), Err(SchedulerError::Deadlock { mutex: other, task: b }));
+    }
+
+    #[test]
+    fn test_cancel_update_and_introspection() {
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.add_task(1, Duration::from_secs(5));
+        let b = scheduler.add_task(2, Duration::from_secs(5));
+        let c = scheduler.add_task(2, Duration::from_secs(1));
+        let ids = |tasks: Vec<Task>| tasks.iter().map(|task| task.id).collect::<Vec<u32>>();
+        assert_eq!(ids(scheduler.pending()), vec![c, b, a]);
+        assert_eq!(scheduler.peek().unwrap().id, c);
+
+        scheduler.update_priority(a, 5).unwrap();
+        scheduler.update_deadline(b, Duration::ZERO).unwrap();
+        assert_eq!(ids(scheduler.pending()), vec![a, b, c]);
+        assert_eq!(scheduler.cancel(b).unwrap().id, b);
+        assert_eq!(scheduler.cancel(b).unwrap_err(), SchedulerError::TaskNotFound(b));
+        assert_eq!(scheduler.update_priority(99, 1), Err(SchedulerError::TaskNotFound(99)));
+
+        assert_eq!(scheduler.get_next_task().unwrap().id, a);
+        scheduler.update_priority(a, 0).unwrap();
+        scheduler.preempt(a).unwrap();
+        assert_eq!(ids(scheduler.pending()), vec![c, a]);
+        assert_eq!(scheduler.task_timing(a).unwrap().priority, Priority(0));
+
+        // Tasks waiting for predecessors can be updated before they are queued
+        let d = scheduler.add_task_with(1, Duration::from_secs(5), None, &[c]).unwrap();
+        scheduler.update_priority(d, 9).unwrap();
+        scheduler.update_deadline(d, Duration::from_secs(1)).unwrap();
+        assert_eq!(scheduler.get_next_task().unwrap().id, c);
+        scheduler.complete_task(c).unwrap();
+        assert_eq!(scheduler.peek().map(|task| (task.id, task.priority)), Some((d, Priority(9))));
+    }
+
+    #[test]
+    fn test_cancel_mutex_holders_and_waiters() {
+        let mut scheduler = Scheduler::new();
+        let resource = scheduler.create_mutex(LockProtocol::Inheritance);
+        let low = scheduler.add_task(1, Duration::from_secs(3));
+        let medium = scheduler.add_task(2, Duration::from_secs(3));
+        let high = scheduler.add_task(3, Duration::from_secs(3));
+
+        // A preempted task leaves the ready queue and its timing behind
+        assert_eq!(scheduler.get_next_task().unwrap().id, high);
+        scheduler.preempt(high).unwrap();
+        scheduler.cancel(high).unwrap();
+        assert!(scheduler.task_timing(high).is_none());
+
+        assert_eq!(scheduler.get_next_task().unwrap().id, medium);
+        assert_eq!(scheduler.get_next_task().unwrap().id, low);
+        assert_eq!(scheduler.lock(resource, low), Ok(LockOutcome::Acquired));
+        assert_eq!(scheduler.lock(resource, medium), Ok(LockOutcome::Blocked));
+        assert_eq!(scheduler.effective_priority(low), Some(Priority(2)));
+
+        // Cancelling the running owner hands the mutex to the blocked waiter
+        scheduler.cancel(low).unwrap();
+        assert_eq!(scheduler.get_next_task().unwrap().id, medium);
+        assert_eq!(scheduler.lock(resource, medium), Ok(LockOutcome::Acquired));
+
+        // A cancelled waiter no longer raises the owner's priority
+        let waiter = scheduler.add_task(5, Duration::from_secs(1));
+        assert_eq!(scheduler.get_next_task().unwrap().id, waiter);
+        assert_eq!(scheduler.lock(resource, waiter), Ok(LockOutcome::Blocked));
+        assert_eq!(scheduler.effective_priority(medium), Some(Priority(5)));
+        assert_eq!(scheduler.cancel(waiter).unwrap().priority, Priority(5));
+        assert_eq!(scheduler.effective_priority(medium), Some(Priority(2)));
+        assert_eq!(scheduler.unlock(resource, medium), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_payloads_and_dependencies() {
+        let mut scheduler = Scheduler::new();
+        let trace = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = |name: &'static str| {
+            let trace = std::sync::Arc::clone(&trace);
+            move || trace.lock().unwrap().push(name)
+        };
+
+        // perception -> planning -> control, with a low priority logger depending on perception
+        let perception = scheduler.add_task_with(1, Duration::from_secs(1), Some(TaskPayload::closure(log("perception"))), &[]).unwrap();
+        let planning_log = log("planning");
+        let planning = scheduler
+            .add_task_with(3, Duration::from_secs(1), Some(TaskPayload::future(async move { planning_log() })), &[perception])
+            .unwrap();
+        let control = scheduler.add_task_with(5, Duration::from_secs(1), Some(TaskPayload::closure(log("control"))), &[planning]).unwrap();
+        let logger = scheduler.add_task_with(0, Duration::from_secs(1), Some(TaskPayload::closure(log("logger"))), &[perception]).unwrap();
+        assert_eq!(scheduler.waiting(), vec![planning, control, logger]);
+        assert_eq!(scheduler.add_task_with(1, Duration::ZERO, None, &[99]).unwrap_err(), SchedulerError::TaskNotFound(99));
+
+        while let Some(task) = scheduler.get_next_task() {
+            if let Some(payload) = scheduler.take_payload(task.id) {
+                payload.run().await;
+            }
+            scheduler.complete_task(task.id).unwrap();
+        }
+        assert_eq!(*trace.lock().unwrap(), vec!["perception", "planning", "control", "logger"]);
+        assert!(scheduler.waiting().is_empty());
+
+        // Completed dependencies are already satisfied; cancelling cascades
+        let first = scheduler.add_task_with(1, Duration::ZERO, None, &[control]).unwrap();
+        let second = scheduler.add_task_with(1, Duration::ZERO, None, &[first]).unwrap();
+        assert_eq!(scheduler.waiting(), vec![second]);
+        scheduler.cancel(first).unwrap();
+        assert!(scheduler.waiting().is_empty());
+        assert!(scheduler.peek().is_none());
+
+        // Nothing is kept about cancelled tasks once their dependents are gone
+        assert!(scheduler.dependents.is_empty());
+    }
 }
\ No newline at end of file
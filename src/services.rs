@@ -0,0 +1,674 @@
+use std::any::type_name;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::ipc::{IpcError, Message, IPC};
+use crate::logging;
+use crate::sync::lock_or_recover;
+
+const LOG_TARGET: &str = "services";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceError {
+    ServiceNotFound(String),
+    AlreadyRegistered(String),
+    TypeMismatch { name: String, expected: &'static str, found: &'static str },
+    Timeout { name: String, after: Duration },
+    Serialization { name: String, message: String },
+    Ipc(IpcError),
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceError::ServiceNotFound(name) => write!(f, "No service or action: {}", name),
+            ServiceError::AlreadyRegistered(name) => write!(f, "{} is already served", name),
+            ServiceError::TypeMismatch { name, expected, found } => {
+                write!(f, "{} uses {}, not {}", name, expected, found)
+            },
+            ServiceError::Timeout { name, after } => write!(f, "No reply from {} within {:?}", name, after),
+            ServiceError::Serialization { name, message } => write!(f, "Cannot (de)serialize message for {}: {}", name, message),
+            ServiceError::Ipc(e) => write!(f, "IPC error: {}", e),
+        }
+    }
+}
+
+impl Error for ServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServiceError::Ipc(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<IpcError> for ServiceError {
+    fn from(e: IpcError) -> Self {
+        ServiceError::Ipc(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum EnvelopeKind {
+    Request,
+    Response,
+    Goal,
+    Feedback,
+    Result,
+    Cancel,
+}
+
+// Identifies a call or goal. The token is random per registry, so clients of
+// different registries can share a mailbox without taking each other's replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct CallId {
+    token: u64,
+    seq: u64,
+}
+
+impl fmt::Display for CallId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:x}/{}", self.token, self.seq)
+    }
+}
+
+// Wire format of service and action traffic inside IPC messages
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    kind: EnvelopeKind,
+    name: String,
+    call: CallId,
+    body: serde_json::Value,
+}
+
+impl Envelope {
+    fn is(&self, kind: EnvelopeKind, name: &str, call: CallId) -> bool {
+        self.kind == kind && self.name == name && self.call == call
+    }
+}
+
+fn encode<T: Serialize>(kind: EnvelopeKind, name: &str, call: CallId, body: &T) -> Result<String, ServiceError> {
+    let serialization = |e: serde_json::Error| ServiceError::Serialization { name: name.to_string(), message: e.to_string() };
+    let body = serde_json::to_value(body).map_err(serialization)?;
+    serde_json::to_string(&Envelope { kind, name: name.to_string(), call, body }).map_err(serialization)
+}
+
+fn decode<T: DeserializeOwned>(envelope: Envelope) -> Result<T, ServiceError> {
+    serde_json::from_value(envelope.body).map_err(|e| ServiceError::Serialization {
+        name: envelope.name,
+        message: e.to_string(),
+    })
+}
+
+// Takes the first message addressed to `pid` that is an envelope accepted by `matches`
+fn take_envelope<F>(ipc: &IPC, pid: u32, mut matches: F) -> Option<(u32, Envelope)>
+where
+    F: FnMut(&Envelope) -> bool,
+{
+    let mut found = None;
+    ipc.take_message(pid, |message: &Message| match serde_json::from_str::<Envelope>(&message.content) {
+        Ok(envelope) if matches(&envelope) => {
+            found = Some((message.sender, envelope));
+            true
+        },
+        _ => false,
+    })?;
+    found
+}
+
+// Retries `take` whenever a message is delivered to `pid`, for up to
+// `timeout` on the IPC's clock. Fails early if the mailbox is removed.
+async fn wait_for<T, F>(ipc: &IPC, pid: u32, name: &str, timeout: Duration, mut take: F) -> Result<T, ServiceError>
+where
+    F: FnMut() -> Option<T>,
+{
+    let mut expired = ipc.clock().sleep(timeout);
+    loop {
+        // Taken before checking, so a message arriving in between is not missed
+        let delivery = ipc.next_delivery(pid);
+        if let Some(found) = take() {
+            return Ok(found);
+        }
+        tokio::select! {
+            biased;
+            delivered = delivery => delivered?,
+            _ = &mut expired => return Err(ServiceError::Timeout { name: name.to_string(), after: timeout }),
+        }
+    }
+}
+
+fn is_reply(kind: EnvelopeKind) -> bool {
+    matches!(kind, EnvelopeKind::Response | EnvelopeKind::Feedback | EnvelopeKind::Result)
+}
+
+struct Endpoint {
+    server: u32,
+    // Type names of the request and response, or of the goal, feedback and result
+    types: Vec<&'static str>,
+}
+
+// Named request/response services and long-running actions, carried as JSON
+// envelopes in the existing IPC mailboxes of the server and client processes
+#[derive(Clone)]
+pub struct ServiceRegistry {
+    ipc: IPC,
+    endpoints: Arc<Mutex<HashMap<String, Endpoint>>>,
+    token: u64,
+    next_seq: Arc<AtomicU64>,
+    // Calls and goals of this registry still waiting for replies, by name;
+    // replies to any other call of this registry arrived too late and are discarded
+    pending: Arc<Mutex<HashSet<(String, u64)>>>,
+}
+
+impl ServiceRegistry {
+    #[allow(dead_code)]
+    pub fn new(ipc: IPC) -> Self {
+        ServiceRegistry {
+            ipc,
+            endpoints: Arc::new(Mutex::new(HashMap::new())),
+            token: rand::random(),
+            next_seq: Arc::new(AtomicU64::new(1)),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn require_mailbox(&self, pid: u32) -> Result<(), ServiceError> {
+        match self.ipc.has_mailbox(pid) {
+            true => Ok(()),
+            false => Err(ServiceError::Ipc(IpcError::MailboxNotFound(pid))),
+        }
+    }
+
+    fn register(&self, name: &str, server: u32, types: Vec<&'static str>) -> Result<(), ServiceError> {
+        self.require_mailbox(server)?;
+        let mut endpoints = lock_or_recover(&self.endpoints, LOG_TARGET, "register");
+        if endpoints.contains_key(name) {
+            return Err(ServiceError::AlreadyRegistered(name.to_string()));
+        }
+        endpoints.insert(name.to_string(), Endpoint { server, types });
+        logging::debug(LOG_TARGET, &format!("PID {} serves {}", server, name));
+        Ok(())
+    }
+
+    fn unregister(&self, name: &str) {
+        lock_or_recover(&self.endpoints, LOG_TARGET, "unregister").remove(name);
+    }
+
+    // The PID serving `name`, after checking that it uses the caller's types
+    fn lookup(&self, name: &str, types: &[&'static str]) -> Result<u32, ServiceError> {
+        let endpoints = lock_or_recover(&self.endpoints, LOG_TARGET, "lookup");
+        let endpoint = endpoints.get(name).ok_or_else(|| ServiceError::ServiceNotFound(name.to_string()))?;
+        for (expected, found) in endpoint.types.iter().zip(types) {
+            if expected != found {
+                return Err(ServiceError::TypeMismatch { name: name.to_string(), expected, found });
+            }
+        }
+        Ok(endpoint.server)
+    }
+
+    // A fresh call id for `name`, pending until `finish` is called with it
+    fn start(&self, name: &str) -> CallId {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        lock_or_recover(&self.pending, LOG_TARGET, "start").insert((name.to_string(), seq));
+        CallId { token: self.token, seq }
+    }
+
+    fn finish(&self, name: &str, call: CallId) {
+        lock_or_recover(&self.pending, LOG_TARGET, "finish").remove(&(name.to_string(), call.seq));
+    }
+
+    fn is_late(&self, pending: &HashSet<(String, u64)>, envelope: &Envelope) -> bool {
+        envelope.call.token == self.token && !pending.contains(&(envelope.name.clone(), envelope.call.seq))
+    }
+
+    // Takes the reply in the mailbox of `client` that `matches` accepts.
+    // Replies to calls and goals of this registry that are no longer pending
+    // are discarded on the way, so they do not pile up in the mailbox.
+    fn take_reply<F>(&self, client: u32, mut matches: F) -> Option<Envelope>
+    where
+        F: FnMut(&Envelope) -> bool,
+    {
+        loop {
+            let pending = lock_or_recover(&self.pending, LOG_TARGET, "take_reply");
+            let accept = |envelope: &Envelope| is_reply(envelope.kind) && (matches(envelope) || self.is_late(&pending, envelope));
+            let (server, envelope) = take_envelope(&self.ipc, client, accept)?;
+            drop(pending);
+            if matches(&envelope) {
+                return Some(envelope);
+            }
+            logging::debug(LOG_TARGET, &format!(
+                "Dropping late {:?} {} from PID {} for {}",
+                envelope.kind, envelope.call, server, envelope.name
+            ));
+        }
+    }
+
+    // Serves `name` from the mailbox of `server`
+    #[allow(dead_code)]
+    pub fn advertise_service<Req, Resp>(&self, name: &str, server: u32) -> Result<ServiceServer<Req, Resp>, ServiceError>
+    where
+        Req: DeserializeOwned,
+        Resp: Serialize,
+    {
+        self.register(name, server, vec![type_name::<Req>(), type_name::<Resp>()])?;
+        Ok(ServiceServer { registry: self.clone(), name: name.to_string(), server, _types: PhantomData })
+    }
+
+    // Calls `name` from the mailbox of `client`
+    #[allow(dead_code)]
+    pub fn service_client<Req, Resp>(&self, name: &str, client: u32) -> Result<ServiceClient<Req, Resp>, ServiceError>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        self.require_mailbox(client)?;
+        Ok(ServiceClient { registry: self.clone(), name: name.to_string(), client, _types: PhantomData })
+    }
+
+    #[allow(dead_code)]
+    pub fn advertise_action<Goal, Feedback, Outcome>(
+        &self,
+        name: &str,
+        server: u32,
+    ) -> Result<ActionServer<Goal, Feedback, Outcome>, ServiceError>
+    where
+        Goal: DeserializeOwned,
+        Feedback: Serialize,
+        Outcome: Serialize,
+    {
+        self.register(name, server, vec![type_name::<Goal>(), type_name::<Feedback>(), type_name::<Outcome>()])?;
+        Ok(ActionServer { registry: self.clone(), name: name.to_string(), server, _types: PhantomData })
+    }
+
+    #[allow(dead_code)]
+    pub fn action_client<Goal, Feedback, Outcome>(&self, name: &str, client: u32) -> Result<ActionClient<Goal, Feedback, Outcome>, ServiceError>
+    where
+        Goal: Serialize,
+        Feedback: DeserializeOwned,
+        Outcome: DeserializeOwned,
+    {
+        self.require_mailbox(client)?;
+        Ok(ActionClient { registry: self.clone(), name: name.to_string(), client, _types: PhantomData })
+    }
+}
+
+// Unregisters the service when dropped
+pub struct ServiceServer<Req, Resp> {
+    registry: ServiceRegistry,
+    name: String,
+    server: u32,
+    _types: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req: DeserializeOwned, Resp: Serialize> ServiceServer<Req, Resp> {
+    // Answers every queued request with `handler`; returns how many were answered.
+    // Call this from the server process's loop.
+    #[allow(dead_code)]
+    pub fn handle_requests<F>(&self, mut handler: F) -> usize
+    where
+        F: FnMut(Req) -> Resp,
+    {
+        let mut handled = 0;
+        let is_request = |envelope: &Envelope| envelope.kind == EnvelopeKind::Request && envelope.name == self.name;
+        while let Some((client, envelope)) = take_envelope(&self.registry.ipc, self.server, is_request) {
+            let call = envelope.call;
+            let reply = decode::<Req>(envelope)
+                .map(&mut handler)
+                .and_then(|response| encode(EnvelopeKind::Response, &self.name, call, &response))
+                .and_then(|content| Ok(self.registry.ipc.send_message(self.server, client, content)?));
+            if let Err(e) = reply {
+                logging::error(LOG_TARGET, &format!("Dropping request {} from PID {}: {}", call, client, e));
+            }
+            handled += 1;
+        }
+        handled
+    }
+}
+
+impl<Req, Resp> Drop for ServiceServer<Req, Resp> {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.name);
+    }
+}
+
+pub struct ServiceClient<Req, Resp> {
+    registry: ServiceRegistry,
+    name: String,
+    client: u32,
+    _types: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req: Serialize, Resp: DeserializeOwned> ServiceClient<Req, Resp> {
+    // Sends `request` and waits up to `timeout` for the response. A response
+    // that arrives after the timeout is discarded.
+    #[allow(dead_code)]
+    pub async fn call(&self, request: &Req, timeout: Duration) -> Result<Resp, ServiceError> {
+        let server = self.registry.lookup(&self.name, &[type_name::<Req>(), type_name::<Resp>()])?;
+        let call = self.registry.start(&self.name);
+        let response = self.exchange(server, call, request, timeout).await;
+        self.registry.finish(&self.name, call);
+        response
+    }
+
+    async fn exchange(&self, server: u32, call: CallId, request: &Req, timeout: Duration) -> Result<Resp, ServiceError> {
+        let content = encode(EnvelopeKind::Request, &self.name, call, request)?;
+        self.registry.ipc.send_message(self.client, server, content)?;
+
+        let is_response = |envelope: &Envelope| envelope.is(EnvelopeKind::Response, &self.name, call);
+        let ipc = &self.registry.ipc;
+        decode(wait_for(ipc, self.client, &self.name, timeout, || self.registry.take_reply(self.client, is_response)).await?)
+    }
+}
+
+// Final state of an action goal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActionOutcome<R> {
+    Succeeded(R),
+    Aborted(String),
+    Canceled,
+}
+
+// Unregisters the action when dropped
+pub struct ActionServer<Goal, Feedback, Outcome> {
+    registry: ServiceRegistry,
+    name: String,
+    server: u32,
+    _types: PhantomData<fn(Goal) -> (Feedback, Outcome)>,
+}
+
+impl<Goal, Feedback, Outcome> ActionServer<Goal, Feedback, Outcome>
+where
+    Goal: DeserializeOwned,
+    Feedback: Serialize,
+    Outcome: Serialize,
+{
+    // Takes every newly sent goal out of the server's mailbox
+    #[allow(dead_code)]
+    pub fn accept_goals(&self) -> Vec<GoalHandle<Goal, Feedback, Outcome>> {
+        let mut goals = Vec::new();
+        let is_goal = |envelope: &Envelope| envelope.kind == EnvelopeKind::Goal && envelope.name == self.name;
+        while let Some((client, envelope)) = take_envelope(&self.registry.ipc, self.server, is_goal) {
+            let call = envelope.call;
+            match decode::<Goal>(envelope) {
+                Ok(goal) => goals.push(GoalHandle {
+                    registry: self.registry.clone(),
+                    name: self.name.clone(),
+                    server: self.server,
+                    client,
+                    call,
+                    goal,
+                    cancel_requested: Cell::new(false),
+                    _types: PhantomData,
+                }),
+                Err(e) => logging::error(LOG_TARGET, &format!("Dropping goal {} from PID {}: {}", call, client, e)),
+            }
+        }
+        goals
+    }
+}
+
+impl<Goal, Feedback, Outcome> Drop for ActionServer<Goal, Feedback, Outcome> {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.name);
+    }
+}
+
+// Server side of one accepted goal; finish it with `succeed`, `abort` or `cancel`
+pub struct GoalHandle<Goal, Feedback, Outcome> {
+    registry: ServiceRegistry,
+    name: String,
+    server: u32,
+    client: u32,
+    call: CallId,
+    goal: Goal,
+    cancel_requested: Cell<bool>,
+    _types: PhantomData<fn(Feedback) -> Outcome>,
+}
+
+impl<Goal, Feedback: Serialize, Outcome: Serialize> GoalHandle<Goal, Feedback, Outcome> {
+    #[allow(dead_code)]
+    pub fn goal(&self) -> &Goal {
+        &self.goal
+    }
+
+    #[allow(dead_code)]
+    pub fn publish_feedback(&self, feedback: &Feedback) -> Result<(), ServiceError> {
+        let content = encode(EnvelopeKind::Feedback, &self.name, self.call, feedback)?;
+        Ok(self.registry.ipc.send_message(self.server, self.client, content)?)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_cancel_requested(&self) -> bool {
+        let is_cancel = |envelope: &Envelope| envelope.is(EnvelopeKind::Cancel, &self.name, self.call);
+        if take_envelope(&self.registry.ipc, self.server, is_cancel).is_some() {
+            self.cancel_requested.set(true);
+        }
+        self.cancel_requested.get()
+    }
+
+    #[allow(dead_code)]
+    pub fn succeed(self, result: Outcome) -> Result<(), ServiceError> {
+        self.finish(&ActionOutcome::Succeeded(result))
+    }
+
+    #[allow(dead_code)]
+    pub fn abort(self, reason: &str) -> Result<(), ServiceError> {
+        self.finish(&ActionOutcome::<Outcome>::Aborted(reason.to_string()))
+    }
+
+    #[allow(dead_code)]
+    pub fn cancel(self) -> Result<(), ServiceError> {
+        self.finish(&ActionOutcome::<Outcome>::Canceled)
+    }
+
+    fn finish(self, outcome: &ActionOutcome<Outcome>) -> Result<(), ServiceError> {
+        let content = encode(EnvelopeKind::Result, &self.name, self.call, outcome)?;
+        Ok(self.registry.ipc.send_message(self.server, self.client, content)?)
+    }
+}
+
+pub struct ActionClient<Goal, Feedback, Outcome> {
+    registry: ServiceRegistry,
+    name: String,
+    client: u32,
+    _types: PhantomData<fn(Goal) -> (Feedback, Outcome)>,
+}
+
+impl<Goal, Feedback, Outcome> ActionClient<Goal, Feedback, Outcome>
+where
+    Goal: Serialize,
+    Feedback: DeserializeOwned,
+    Outcome: DeserializeOwned,
+{
+    #[allow(dead_code)]
+    pub fn send_goal(&self, goal: &Goal) -> Result<ActionGoal<Feedback, Outcome>, ServiceError> {
+        let types = [type_name::<Goal>(), type_name::<Feedback>(), type_name::<Outcome>()];
+        let server = self.registry.lookup(&self.name, &types)?;
+        let call = self.registry.start(&self.name);
+        let sent = encode(EnvelopeKind::Goal, &self.name, call, goal)
+            .and_then(|content| Ok(self.registry.ipc.send_message(self.client, server, content)?));
+        if let Err(e) = sent {
+            self.registry.finish(&self.name, call);
+            return Err(e);
+        }
+        Ok(ActionGoal {
+            registry: self.registry.clone(),
+            name: self.name.clone(),
+            server,
+            client: self.client,
+            call,
+            _types: PhantomData,
+        })
+    }
+}
+
+// Client side of a sent goal. Replies that arrive after it is dropped are discarded.
+pub struct ActionGoal<Feedback, Outcome> {
+    registry: ServiceRegistry,
+    name: String,
+    server: u32,
+    client: u32,
+    call: CallId,
+    _types: PhantomData<fn() -> (Feedback, Outcome)>,
+}
+
+impl<Feedback: DeserializeOwned, Outcome: DeserializeOwned> ActionGoal<Feedback, Outcome> {
+    // The oldest feedback not read yet
+    #[allow(dead_code)]
+    pub fn try_feedback(&self) -> Result<Option<Feedback>, ServiceError> {
+        let is_feedback = |envelope: &Envelope| envelope.is(EnvelopeKind::Feedback, &self.name, self.call);
+        self.registry.take_reply(self.client, is_feedback).map(decode).transpose()
+    }
+
+    #[allow(dead_code)]
+    pub fn cancel(&self) -> Result<(), ServiceError> {
+        let content = encode(EnvelopeKind::Cancel, &self.name, self.call, &())?;
+        Ok(self.registry.ipc.send_message(self.client, self.server, content)?)
+    }
+
+    // Waits up to `timeout` for the goal to finish. Unread feedback is discarded.
+    #[allow(dead_code)]
+    pub async fn result(self, timeout: Duration) -> Result<ActionOutcome<Outcome>, ServiceError> {
+        let is_result = |envelope: &Envelope| envelope.is(EnvelopeKind::Result, &self.name, self.call);
+        let ipc = &self.registry.ipc;
+        let envelope = wait_for(ipc, self.client, &self.name, timeout, || self.registry.take_reply(self.client, is_result)).await?;
+        let is_ours = |envelope: &Envelope| is_reply(envelope.kind) && envelope.name == self.name && envelope.call == self.call;
+        while take_envelope(ipc, self.client, is_ours).is_some() {}
+        decode(envelope)
+    }
+}
+
+impl<Feedback, Outcome> Drop for ActionGoal<Feedback, Outcome> {
+    fn drop(&mut self) {
+        self.registry.finish(&self.name, self.call);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SimulatedClock;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Pose {
+        x: f64,
+        y: f64,
+    }
+
+    #[tokio::test]
+    async fn test_service_call() {
+        let ipc = IPC::new();
+        for pid in 1..=3 {
+            ipc.create_mailbox(pid);
+        }
+        let registry = ServiceRegistry::new(ipc.clone());
+        assert_eq!(
+            registry.advertise_service::<Pose, f64>("/motion/stop", 4).err(),
+            Some(ServiceError::Ipc(IpcError::MailboxNotFound(4)))
+        );
+        let server = registry.advertise_service::<Pose, f64>("/motion/distance_to", 1).unwrap();
+        assert_eq!(
+            registry.advertise_service::<Pose, f64>("/motion/distance_to", 2).err(),
+            Some(ServiceError::AlreadyRegistered("/motion/distance_to".to_string()))
+        );
+
+        let client = registry.service_client::<Pose, f64>("/motion/distance_to", 2).unwrap();
+        let call = tokio::spawn(async move { client.call(&Pose { x: 3.0, y: 4.0 }, Duration::from_secs(5)).await });
+        while server.handle_requests(|pose| (pose.x * pose.x + pose.y * pose.y).sqrt()) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(call.await.unwrap(), Ok(5.0));
+
+        let wrong_types = registry.service_client::<String, f64>("/motion/distance_to", 2).unwrap();
+        assert!(matches!(wrong_types.call(&"x".to_string(), Duration::ZERO).await, Err(ServiceError::TypeMismatch { .. })));
+        assert!(matches!(
+            registry.service_client::<Pose, f64>("/motion/distance_to", 4),
+            Err(ServiceError::Ipc(IpcError::MailboxNotFound(4)))
+        ));
+
+        // Timeouts run on the IPC's clock
+        let clock = SimulatedClock::new();
+        ipc.set_clock(Arc::new(clock.clone()));
+        let unanswered = registry.service_client::<Pose, f64>("/motion/distance_to", 3).unwrap();
+        let call = tokio::spawn(async move { unanswered.call(&Pose { x: 0.0, y: 0.0 }, Duration::from_secs(60)).await });
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(60));
+        assert!(matches!(call.await.unwrap(), Err(ServiceError::Timeout { .. })));
+
+        // The late response is discarded by the next call from the same mailbox
+        assert_eq!(server.handle_requests(|_| -1.0), 1);
+        let retry = registry.service_client::<Pose, f64>("/motion/distance_to", 3).unwrap();
+        let call = tokio::spawn(async move { retry.call(&Pose { x: 0.0, y: 0.0 }, Duration::from_secs(60)).await });
+        while server.handle_requests(|_| 0.0) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(call.await.unwrap(), Ok(0.0));
+        assert_eq!(ipc.mailbox_stats(3).unwrap().depth, 0);
+
+        // Removing the client's mailbox ends the wait
+        let removed = registry.service_client::<Pose, f64>("/motion/distance_to", 3).unwrap();
+        let call = tokio::spawn(async move { removed.call(&Pose { x: 0.0, y: 0.0 }, Duration::from_secs(60)).await });
+        tokio::task::yield_now().await;
+        ipc.remove_mailbox(3).unwrap();
+        assert_eq!(call.await.unwrap(), Err(ServiceError::Ipc(IpcError::MailboxNotFound(3))));
+
+        drop(server);
+        let missing = registry.service_client::<Pose, f64>("/motion/distance_to", 2).unwrap();
+        assert!(matches!(missing.call(&Pose { x: 0.0, y: 0.0 }, Duration::ZERO).await, Err(ServiceError::ServiceNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_action_feedback_result_and_cancel() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(1);
+        ipc.create_mailbox(2);
+        let registry = ServiceRegistry::new(ipc.clone());
+        let server = registry.advertise_action::<Pose, f64, Pose>("/motion/move_to", 1).unwrap();
+        let client = registry.action_client::<Pose, f64, Pose>("/motion/move_to", 2).unwrap();
+
+        let goal = client.send_goal(&Pose { x: 1.0, y: 2.0 }).unwrap();
+        let mut accepted = server.accept_goals();
+        assert_eq!(accepted.len(), 1);
+        let handle = accepted.remove(0);
+        handle.publish_feedback(&0.5).unwrap();
+        handle.publish_feedback(&1.0).unwrap();
+        assert_eq!(goal.try_feedback().unwrap(), Some(0.5));
+        let target = handle.goal().clone();
+        handle.succeed(target).unwrap();
+        assert_eq!(goal.result(Duration::from_secs(1)).await.unwrap(), ActionOutcome::Succeeded(Pose { x: 1.0, y: 2.0 }));
+
+        let goal = client.send_goal(&Pose { x: 9.0, y: 9.0 }).unwrap();
+        let handle = server.accept_goals().remove(0);
+        assert!(!handle.is_cancel_requested());
+        goal.cancel().unwrap();
+        assert!(handle.is_cancel_requested());
+        handle.cancel().unwrap();
+        assert_eq!(goal.result(Duration::from_secs(1)).await.unwrap(), ActionOutcome::Canceled);
+
+        // Feedback for an abandoned goal does not stay in the client's mailbox
+        let goal = client.send_goal(&Pose { x: 0.0, y: 0.0 }).unwrap();
+        let handle = server.accept_goals().remove(0);
+        drop(goal);
+        handle.publish_feedback(&0.1).unwrap();
+        let goal = client.send_goal(&Pose { x: 5.0, y: 5.0 }).unwrap();
+        assert_eq!(goal.try_feedback().unwrap(), None);
+        assert_eq!(ipc.mailbox_stats(2).unwrap().depth, 0);
+
+        // A client of another registry sharing the mailbox leaves these replies alone
+        let other = ServiceRegistry::new(ipc.clone());
+        let other_server = other.advertise_action::<Pose, f64, Pose>("/motion/dock", 1).unwrap();
+        let other_goal = other.action_client::<Pose, f64, Pose>("/motion/dock", 2).unwrap().send_goal(&Pose { x: 1.0, y: 1.0 }).unwrap();
+        let other_handle = other_server.accept_goals().remove(0);
+        server.accept_goals().remove(0).publish_feedback(&0.7).unwrap();
+        other_handle.publish_feedback(&0.2).unwrap();
+        assert_eq!(other_goal.try_feedback().unwrap(), Some(0.2));
+        assert_eq!(goal.try_feedback().unwrap(), Some(0.7));
+    }
+}
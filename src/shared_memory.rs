@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::logging;
+use crate::memory_manager::{MemoryError, MemoryManager};
+use crate::sync::lock_or_recover;
+
+const LOG_TARGET: &str = "shared_memory";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedMemoryError {
+    Memory(MemoryError),
+    // The buffer still has readers, so it cannot be written again
+    InUse { id: u64, refs: usize },
+}
+
+impl fmt::Display for SharedMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SharedMemoryError::Memory(e) => write!(f, "Shared memory allocation failed: {}", e),
+            SharedMemoryError::InUse { id, refs } => write!(f, "Buffer {} is still held by {} readers", id, refs - 1),
+        }
+    }
+}
+
+impl Error for SharedMemoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SharedMemoryError::Memory(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<MemoryError> for SharedMemoryError {
+    fn from(e: MemoryError) -> Self {
+        SharedMemoryError::Memory(e)
+    }
+}
+
+struct Region {
+    address: usize,
+    len: usize,
+    refs: usize,
+}
+
+// Refcounted buffers carved out of the MemoryManager pool. A publisher loans
+// a buffer, writes into it in place and publishes it; the resulting handles
+// travel in IPC messages and every reader sees the same bytes. The memory
+// goes back to the pool when the last handle is dropped.
+#[derive(Clone)]
+pub struct SharedMemory {
+    memory_manager: Arc<Mutex<MemoryManager>>,
+    regions: Arc<Mutex<HashMap<u64, Region>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl SharedMemory {
+    #[allow(dead_code)]
+    pub fn new(memory_manager: Arc<Mutex<MemoryManager>>) -> Self {
+        SharedMemory {
+            memory_manager,
+            regions: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    // Allocates a `len` byte buffer for writing. Its initial contents are
+    // whatever the pool held before. Empty buffers are refused with ZeroSize.
+    #[allow(dead_code)]
+    pub fn loan(&self, len: usize) -> Result<BufferLoan, SharedMemoryError> {
+        let address = lock_or_recover(&self.memory_manager, LOG_TARGET, "loan").allocate(len)?;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        lock_or_recover(&self.regions, LOG_TARGET, "loan").insert(id, Region { address, len, refs: 1 });
+        Ok(BufferLoan { buffer: SharedBuffer { memory: self.clone(), id, len } })
+    }
+
+    // Number of buffers currently loaned or published
+    #[allow(dead_code)]
+    pub fn buffers(&self) -> usize {
+        lock_or_recover(&self.regions, LOG_TARGET, "buffers").len()
+    }
+
+    fn address(&self, id: u64) -> usize {
+        lock_or_recover(&self.regions, LOG_TARGET, "address")
+            .get(&id)
+            .map(|region| region.address)
+            .expect("a live handle keeps its region")
+    }
+
+    fn refs(&self, id: u64) -> usize {
+        lock_or_recover(&self.regions, LOG_TARGET, "refs").get(&id).map_or(0, |region| region.refs)
+    }
+
+    fn acquire(&self, id: u64) {
+        if let Some(region) = lock_or_recover(&self.regions, LOG_TARGET, "acquire").get_mut(&id) {
+            region.refs += 1;
+        }
+    }
+
+    fn release(&self, id: u64) {
+        let mut regions = lock_or_recover(&self.regions, LOG_TARGET, "release");
+        let Some(region) = regions.get_mut(&id) else {
+            return;
+        };
+        region.refs -= 1;
+        if region.refs > 0 {
+            return;
+        }
+        let region = regions.remove(&id).expect("the region was just found");
+        drop(regions);
+        if let Err(e) = lock_or_recover(&self.memory_manager, LOG_TARGET, "release").deallocate(region.address) {
+            logging::error(LOG_TARGET, &format!("Failed to return buffer {} ({} bytes): {}", id, region.len, e));
+        }
+    }
+}
+
+// Exclusive write access to a buffer that has not been published yet.
+// Dropping it returns the memory to the pool.
+#[derive(Debug)]
+pub struct BufferLoan {
+    buffer: SharedBuffer,
+}
+
+impl BufferLoan {
+    #[allow(dead_code)]
+    pub fn id(&self) -> u64 {
+        self.buffer.id
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.buffer.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len == 0
+    }
+
+    // Runs `write` on the buffer's bytes in the pool, without copying. The
+    // pool stays locked meanwhile, so `write` must not touch other buffers.
+    #[allow(dead_code)]
+    pub fn write<R, F: FnOnce(&mut [u8]) -> R>(&mut self, write: F) -> R {
+        let address = self.buffer.memory.address(self.buffer.id);
+        let mut memory_manager = lock_or_recover(&self.buffer.memory.memory_manager, LOG_TARGET, "write");
+        write(memory_manager.bytes_mut(address).expect("a loaned region stays allocated"))
+    }
+
+    // Ends the loan; the buffer becomes read-only and can be shared
+    #[allow(dead_code)]
+    pub fn publish(self) -> SharedBuffer {
+        self.buffer
+    }
+}
+
+// Read-only handle to a published buffer. Cloning shares the bytes and bumps
+// the reference count instead of copying.
+pub struct SharedBuffer {
+    memory: SharedMemory,
+    id: u64,
+    len: usize,
+}
+
+impl SharedBuffer {
+    #[allow(dead_code)]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Number of handles, including this one
+    pub fn ref_count(&self) -> usize {
+        self.memory.refs(self.id)
+    }
+
+    // Like `BufferLoan::write`, `read` runs with the pool locked
+    #[allow(dead_code)]
+    pub fn read<R, F: FnOnce(&[u8]) -> R>(&self, read: F) -> R {
+        let address = self.memory.address(self.id);
+        let memory_manager = lock_or_recover(&self.memory.memory_manager, LOG_TARGET, "read");
+        read(memory_manager.bytes(address).expect("a published region stays allocated"))
+    }
+
+    // Loans the buffer out again once every reader has returned its handle,
+    // so a publisher can reuse it for the next frame without reallocating
+    #[allow(dead_code)]
+    pub fn try_into_loan(self) -> Result<BufferLoan, (SharedBuffer, SharedMemoryError)> {
+        match self.ref_count() {
+            1 => Ok(BufferLoan { buffer: self }),
+            refs => {
+                let error = SharedMemoryError::InUse { id: self.id, refs };
+                Err((self, error))
+            },
+        }
+    }
+}
+
+impl Clone for SharedBuffer {
+    fn clone(&self) -> Self {
+        self.memory.acquire(self.id);
+        SharedBuffer { memory: self.memory.clone(), id: self.id, len: self.len }
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        self.memory.release(self.id);
+    }
+}
+
+impl fmt::Debug for SharedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedBuffer").field("id", &self.id).field("len", &self.len).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::{IpcError, IPC};
+
+    #[test]
+    fn test_loan_publish_and_return() {
+        let memory_manager = Arc::new(Mutex::new(MemoryManager::new(1024)));
+        let memory = SharedMemory::new(Arc::clone(&memory_manager));
+
+        // An empty loan would alias the next buffer's address
+        assert_eq!(memory.loan(0).err(), Some(SharedMemoryError::Memory(MemoryError::ZeroSize)));
+        let mut loan = memory.loan(600).unwrap();
+        loan.write(|bytes| bytes.fill(0xab));
+        let frame = loan.publish();
+        assert!(matches!(memory.loan(600), Err(SharedMemoryError::Memory(MemoryError::OutOfMemory { .. }))));
+
+        let reader = frame.clone();
+        assert_eq!(frame.ref_count(), 2);
+        assert!(reader.read(|bytes| bytes.len() == 600 && bytes.iter().all(|&byte| byte == 0xab)));
+
+        // The publisher gets the buffer back for the next frame once readers are done
+        let (frame, error) = frame.try_into_loan().unwrap_err();
+        assert_eq!(error, SharedMemoryError::InUse { id: frame.id(), refs: 2 });
+        drop(reader);
+        let mut loan = frame.try_into_loan().unwrap();
+        loan.write(|bytes| bytes[0] = 1);
+        drop(loan);
+        assert_eq!(memory.buffers(), 0);
+        assert!(memory_manager.lock().unwrap().allocate(1024).is_ok());
+    }
+
+    #[test]
+    fn test_buffers_over_ipc() {
+        let memory = SharedMemory::new(Arc::new(Mutex::new(MemoryManager::new(4096))));
+        let ipc = IPC::new();
+        ipc.create_mailbox(2);
+        ipc.create_mailbox(3);
+
+        let mut loan = memory.loan(3000).unwrap();
+        loan.write(|bytes| bytes[..4].copy_from_slice(b"scan"));
+        let scan = loan.publish();
+        ipc.send_buffer(1, 2, "lidar", scan.clone()).unwrap();
+        ipc.send_buffer(1, 3, "lidar", scan).unwrap();
+        ipc.send_message(1, 3, "text".to_string()).unwrap();
+
+        let (header, received) = ipc.recv_buffer(2).unwrap().unwrap();
+        assert_eq!(header.frame_id, "lidar");
+        assert!(received.read(|bytes| bytes.starts_with(b"scan")));
+        assert_eq!(received.ref_count(), 2);
+        drop(received);
+
+        // Undelivered messages give their references back with the mailbox
+        ipc.remove_mailbox(3).unwrap();
+        assert_eq!(memory.buffers(), 0);
+        assert_eq!(ipc.recv_buffer(3).err(), Some(IpcError::MailboxNotFound(3)));
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::core_system::{CoreSystem, CoreSystemError, ProcessBody, ProcessFactory, ProcessState, DEFAULT_PRIORITY, RESTART_EXIT_CODE};
+use crate::logging;
+
+const LOG_TARGET: &str = "supervisor";
+
+// Exit code of a supervisor that gave up after exceeding its restart intensity
+pub const SUPERVISOR_FAILED_EXIT_CODE: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestartStrategy {
+    // Only the child that died is restarted
+    #[allow(dead_code)]
+    OneForOne,
+    // All children are restarted when one dies
+    #[allow(dead_code)]
+    OneForAll,
+    // The child that died and every child declared after it are restarted
+    #[allow(dead_code)]
+    RestForOne,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChildRestart {
+    // Always restarted
+    Permanent,
+    // Restarted only if it exits with a non-zero exit code
+    #[allow(dead_code)]
+    Transient,
+    // Never restarted
+    Temporary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SupervisorState {
+    Stopped,
+    Running,
+    // Gave up after too many restarts within the intensity window
+    Failed,
+}
+
+pub struct ChildSpec {
+    name: String,
+    priority: u8,
+    restart: ChildRestart,
+    factory: ProcessFactory,
+}
+
+impl ChildSpec {
+    #[allow(dead_code)]
+    pub fn new<F, Fut>(name: &str, priority: u8, restart: ChildRestart, factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = i32> + Send + 'static,
+    {
+        ChildSpec {
+            name: name.to_string(),
+            priority,
+            restart,
+            factory: Arc::new(move || Box::pin(factory()) as ProcessBody),
+        }
+    }
+}
+
+enum ChildKind {
+    Worker(ChildSpec),
+    Supervisor(Supervisor),
+}
+
+struct Child {
+    kind: ChildKind,
+    pid: Option<u32>,
+    restart_at: Option<Instant>,
+    restart_count: u32,
+}
+
+impl Child {
+    fn restart(&self) -> ChildRestart {
+        match &self.kind {
+            ChildKind::Worker(spec) => spec.restart,
+            ChildKind::Supervisor(_) => ChildRestart::Permanent,
+        }
+    }
+}
+
+// Starts a set of child processes and restarts them according to a strategy
+// when they terminate. Supervisors can be nested to build supervision trees;
+// the root supervisor's `supervise` must be called periodically.
+pub struct Supervisor {
+    name: String,
+    strategy: RestartStrategy,
+    max_restarts: u32,
+    window: Duration,
+    backoff_initial: Duration,
+    backoff_max: Duration,
+    children: Vec<Child>,
+    restarts: VecDeque<Instant>,
+    pid: Option<u32>,
+    state: SupervisorState,
+}
+
+impl Supervisor {
+    #[allow(dead_code)]
+    pub fn new(name: &str, strategy: RestartStrategy) -> Self {
+        Supervisor {
+            name: name.to_string(),
+            strategy,
+            max_restarts: 3,
+            window: Duration::from_secs(5),
+            backoff_initial: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(5),
+            children: Vec::new(),
+            restarts: VecDeque::new(),
+            pid: None,
+            state: SupervisorState::Stopped,
+        }
+    }
+
+    // Gives up once more than `max_restarts` restarts happen within `window`
+    #[allow(dead_code)]
+    pub fn set_intensity(&mut self, max_restarts: u32, window: Duration) {
+        self.max_restarts = max_restarts;
+        self.window = window;
+    }
+
+    // Delay before a child is restarted, doubling with each restart inside the window
+    #[allow(dead_code)]
+    pub fn set_backoff(&mut self, initial: Duration, max: Duration) {
+        self.backoff_initial = initial;
+        self.backoff_max = max;
+    }
+
+    #[allow(dead_code)]
+    pub fn add_worker(&mut self, spec: ChildSpec) {
+        self.add_child(ChildKind::Worker(spec));
+    }
+
+    #[allow(dead_code)]
+    pub fn add_supervisor(&mut self, supervisor: Supervisor) {
+        self.add_child(ChildKind::Supervisor(supervisor));
+    }
+
+    fn add_child(&mut self, kind: ChildKind) {
+        self.children.push(Child { kind, pid: None, restart_at: None, restart_count: 0 });
+    }
+
+    #[allow(dead_code)]
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    #[allow(dead_code)]
+    pub fn state(&self) -> SupervisorState {
+        self.state
+    }
+
+    // Current PID of each child in declaration order, None while it is down
+    #[allow(dead_code)]
+    pub fn child_pids(&self) -> Vec<Option<u32>> {
+        self.children.iter().map(|child| child.pid).collect()
+    }
+
+    // Creates the supervisor process and starts every child under it
+    pub fn start(&mut self, core: &mut CoreSystem, parent: Option<u32>) -> Result<u32, CoreSystemError> {
+        let pid = core.create_process_with(&self.name, parent, DEFAULT_PRIORITY)?;
+        self.pid = Some(pid);
+        self.state = SupervisorState::Running;
+        self.restarts.clear();
+        for index in 0..self.children.len() {
+            self.children[index].restart_count = 0;
+            self.start_child(core, index)?;
+        }
+        logging::info(LOG_TARGET, &format!("Started supervisor {} with PID: {}", self.name, pid));
+        Ok(pid)
+    }
+
+    // Terminates every child, last declared first, and then the supervisor itself
+    pub fn stop(&mut self, core: &mut CoreSystem, exit_code: i32) {
+        for index in (0..self.children.len()).rev() {
+            self.stop_child(core, index);
+        }
+        if let Some(pid) = self.pid {
+            if is_alive(core, pid) {
+                let _ = core.terminate(pid, exit_code);
+            }
+        }
+        self.state = SupervisorState::Stopped;
+    }
+
+    // Detects terminated children and restarts them. Call this periodically,
+    // e.g. once per main loop iteration.
+    #[allow(dead_code)]
+    pub fn supervise(&mut self, core: &mut CoreSystem) -> Result<SupervisorState, CoreSystemError> {
+        if self.state != SupervisorState::Running {
+            return Ok(self.state);
+        }
+        if !self.pid.is_some_and(|pid| is_alive(core, pid)) {
+            // Terminated from outside, e.g. by a system shutdown
+            self.stop(core, RESTART_EXIT_CODE);
+            return Ok(self.state);
+        }
+
+        for child in self.children.iter_mut() {
+            if let ChildKind::Supervisor(supervisor) = &mut child.kind {
+                supervisor.supervise(core)?;
+            }
+        }
+
+        let now = core.now();
+        while self.restarts.front().is_some_and(|&at| now.duration_since(at) > self.window) {
+            self.restarts.pop_front();
+        }
+
+        for index in 0..self.children.len() {
+            let pid = match self.children[index].pid {
+                Some(pid) if !is_alive(core, pid) => pid,
+                _ => continue,
+            };
+            let exit_code = core.exit_code(pid).unwrap_or(RESTART_EXIT_CODE);
+            self.children[index].pid = None;
+            let restart = match self.children[index].restart() {
+                ChildRestart::Permanent => true,
+                ChildRestart::Transient => exit_code != 0,
+                ChildRestart::Temporary => false,
+            };
+            logging::warn(LOG_TARGET, &format!(
+                "Child PID {} of supervisor {} exited with code {}",
+                pid, self.name, exit_code
+            ));
+            if !restart {
+                continue;
+            }
+
+            self.restarts.push_back(now);
+            if self.restarts.len() > self.max_restarts as usize {
+                logging::error(LOG_TARGET, &format!(
+                    "Supervisor {} exceeded {} restarts in {:?}, giving up",
+                    self.name, self.max_restarts, self.window
+                ));
+                self.stop(core, SUPERVISOR_FAILED_EXIT_CODE);
+                self.state = SupervisorState::Failed;
+                return Ok(self.state);
+            }
+
+            let affected = match self.strategy {
+                RestartStrategy::OneForOne => index..index + 1,
+                RestartStrategy::OneForAll => 0..self.children.len(),
+                RestartStrategy::RestForOne => index..self.children.len(),
+            };
+            for sibling in affected {
+                if sibling != index {
+                    self.stop_child(core, sibling);
+                    if self.children[sibling].restart() == ChildRestart::Temporary {
+                        continue;
+                    }
+                }
+                self.schedule_restart(sibling, now);
+            }
+        }
+
+        for index in 0..self.children.len() {
+            if self.children[index].restart_at.is_some_and(|at| at <= now) {
+                self.children[index].restart_at = None;
+                self.start_child(core, index)?;
+            }
+        }
+        Ok(self.state)
+    }
+
+    fn schedule_restart(&mut self, index: usize, now: Instant) {
+        let window_is_quiet = self.restarts.len() <= 1;
+        let child = &mut self.children[index];
+        if window_is_quiet {
+            child.restart_count = 0;
+        }
+        let factor = 2u32.saturating_pow(child.restart_count);
+        let delay = self.backoff_initial.saturating_mul(factor).min(self.backoff_max);
+        child.restart_count += 1;
+        child.restart_at = Some(now + delay);
+    }
+
+    fn start_child(&mut self, core: &mut CoreSystem, index: usize) -> Result<(), CoreSystemError> {
+        let parent = self.pid;
+        let child = &mut self.children[index];
+        let pid = match &mut child.kind {
+            ChildKind::Worker(spec) => core.spawn(&spec.name, parent, spec.priority, (spec.factory)())?,
+            ChildKind::Supervisor(supervisor) => supervisor.start(core, parent)?,
+        };
+        child.pid = Some(pid);
+        Ok(())
+    }
+
+    fn stop_child(&mut self, core: &mut CoreSystem, index: usize) {
+        let child = &mut self.children[index];
+        child.restart_at = None;
+        match &mut child.kind {
+            ChildKind::Supervisor(supervisor) => supervisor.stop(core, RESTART_EXIT_CODE),
+            ChildKind::Worker(_) => {
+                if let Some(pid) = child.pid.filter(|&pid| is_alive(core, pid)) {
+                    let _ = core.terminate(pid, RESTART_EXIT_CODE);
+                }
+            },
+        }
+        child.pid = None;
+    }
+}
+
+fn is_alive(core: &CoreSystem, pid: u32) -> bool {
+    !matches!(core.process_state(pid), Some(ProcessState::Terminated) | None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::core_system::yield_now;
+
+    fn long_running(name: &str) -> ChildSpec {
+        ChildSpec::new(name, DEFAULT_PRIORITY, ChildRestart::Permanent, || async {
+            loop {
+                yield_now().await;
+            }
+        })
+    }
+
+    fn supervisor(strategy: RestartStrategy) -> Supervisor {
+        let mut supervisor = Supervisor::new("root", strategy);
+        supervisor.set_backoff(Duration::ZERO, Duration::ZERO);
+        supervisor
+    }
+
+    #[test]
+    fn test_one_for_one() {
+        let mut core = CoreSystem::new();
+        let mut supervisor = supervisor(RestartStrategy::OneForOne);
+        supervisor.add_worker(long_running("perception"));
+        supervisor.add_worker(long_running("planning"));
+        let root = supervisor.start(&mut core, None).unwrap();
+
+        let before = supervisor.child_pids();
+        core.terminate(before[0].unwrap(), -9).unwrap();
+        assert_eq!(supervisor.supervise(&mut core).unwrap(), SupervisorState::Running);
+
+        let after = supervisor.child_pids();
+        assert_ne!(after[0], before[0]);
+        assert_eq!(after[1], before[1]);
+        let table = core.process_table().unwrap();
+        let restarted = table.iter().find(|info| Some(info.pid) == after[0]).unwrap();
+        assert_eq!(restarted.name, "perception");
+        assert_eq!(restarted.parent, Some(root));
+    }
+
+    #[test]
+    fn test_one_for_all_and_rest_for_one() {
+        let mut core = CoreSystem::new();
+        let mut one_for_all = supervisor(RestartStrategy::OneForAll);
+        one_for_all.add_worker(long_running("a"));
+        one_for_all.add_worker(long_running("b"));
+        one_for_all.start(&mut core, None).unwrap();
+        let before = one_for_all.child_pids();
+        core.terminate(before[1].unwrap(), -9).unwrap();
+        one_for_all.supervise(&mut core).unwrap();
+        let after = one_for_all.child_pids();
+        assert!(after.iter().zip(&before).all(|(a, b)| a.is_some() && a != b));
+        assert_eq!(core.exit_code(before[0].unwrap()), Some(RESTART_EXIT_CODE));
+
+        let mut rest_for_one = supervisor(RestartStrategy::RestForOne);
+        rest_for_one.add_worker(long_running("driver"));
+        rest_for_one.add_worker(long_running("filter"));
+        rest_for_one.add_worker(long_running("controller"));
+        rest_for_one.start(&mut core, None).unwrap();
+        let before = rest_for_one.child_pids();
+        core.terminate(before[1].unwrap(), -9).unwrap();
+        rest_for_one.supervise(&mut core).unwrap();
+        let after = rest_for_one.child_pids();
+        assert_eq!(after[0], before[0]);
+        assert_ne!(after[1], before[1]);
+        assert_ne!(after[2], before[2]);
+    }
+
+    #[test]
+    fn test_restart_policies_and_intensity() {
+        let mut core = CoreSystem::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&starts);
+        let mut supervisor = supervisor(RestartStrategy::OneForOne);
+        supervisor.set_intensity(2, Duration::from_secs(60));
+        supervisor.add_worker(ChildSpec::new("crashing", DEFAULT_PRIORITY, ChildRestart::Permanent, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { 1 }
+        }));
+        supervisor.add_worker(ChildSpec::new("oneshot", DEFAULT_PRIORITY, ChildRestart::Transient, || async { 0 }));
+        let root = supervisor.start(&mut core, None).unwrap();
+
+        // The crashing child is restarted twice, the third crash exceeds the intensity
+        let mut state = SupervisorState::Running;
+        for _ in 0..20 {
+            core.step().unwrap();
+            state = supervisor.supervise(&mut core).unwrap();
+            if state != SupervisorState::Running {
+                break;
+            }
+        }
+        assert_eq!(state, SupervisorState::Failed);
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+        assert_eq!(core.exit_code(root), Some(SUPERVISOR_FAILED_EXIT_CODE));
+        assert!(supervisor.child_pids().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_nested_supervisors_and_backoff() {
+        let mut core = CoreSystem::new();
+        let mut sensors = Supervisor::new("sensors", RestartStrategy::OneForOne);
+        sensors.set_intensity(0, Duration::from_secs(60));
+        sensors.add_worker(long_running("lidar"));
+
+        let mut root = Supervisor::new("root", RestartStrategy::OneForOne);
+        root.set_backoff(Duration::from_secs(60), Duration::from_secs(60));
+        root.add_supervisor(sensors);
+        root.start(&mut core, None).unwrap();
+        let sensors_pid = root.child_pids()[0].unwrap();
+
+        // Any failure makes the inner supervisor give up, which the root notices
+        let lidar = core.process_table().unwrap().iter().find(|info| info.name == "lidar").unwrap().pid;
+        core.terminate(lidar, -9).unwrap();
+        root.supervise(&mut core).unwrap();
+        assert_eq!(core.exit_code(sensors_pid), Some(SUPERVISOR_FAILED_EXIT_CODE));
+
+        // The restart is delayed by the backoff
+        assert_eq!(root.child_pids(), vec![None]);
+        assert_eq!(root.state(), SupervisorState::Running);
+    }
+}
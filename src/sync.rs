@@ -0,0 +1,238 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Instant;
+use crate::logging;
+use crate::scheduler::{LockOutcome, LockProtocol, Scheduler, SchedulerError};
+
+const LOG_TARGET: &str = "sync";
+const MAX_FAULT_EVENTS: usize = 256;
+
+// A recovered failure, e.g. a lock poisoned by a panicking holder
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultEvent {
+    pub at: Instant,
+    pub target: String,
+    pub description: String,
+}
+
+fn fault_log() -> &'static Mutex<VecDeque<FaultEvent>> {
+    static FAULTS: OnceLock<Mutex<VecDeque<FaultEvent>>> = OnceLock::new();
+    FAULTS.get_or_init(|| Mutex::new(VecDeque::with_capacity(MAX_FAULT_EVENTS)))
+}
+
+pub fn record_fault(target: &str, description: &str) {
+    logging::warn(target, &format!("Fault recovered: {}", description));
+    push_fault(target, description);
+}
+
+fn push_fault(target: &str, description: &str) {
+    let mut faults = fault_log().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if faults.len() == MAX_FAULT_EVENTS {
+        faults.pop_front();
+    }
+    faults.push_back(FaultEvent {
+        at: Instant::now(),
+        target: target.to_string(),
+        description: description.to_string(),
+    });
+}
+
+// The most recent recovered faults, oldest first
+#[allow(dead_code)]
+pub fn fault_events() -> Vec<FaultEvent> {
+    let faults = fault_log().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    faults.iter().cloned().collect()
+}
+
+// Locks `mutex` even if a previous holder panicked. The state left behind by
+// the panicking holder is kept as is, the poison flag is cleared and a fault
+// event is recorded so the failure is still visible. The fault is only logged
+// once the guard is dropped, since a log sink may need the same lock.
+pub fn lock_or_recover<'a, T>(mutex: &'a Mutex<T>, target: &str, operation: &str) -> RecoveredGuard<'a, T> {
+    match mutex.lock() {
+        Ok(guard) => RecoveredGuard { guard: Some(guard), fault: None },
+        Err(poisoned) => {
+            mutex.clear_poison();
+            let description = format!("lock poisoned before {}", operation);
+            push_fault(target, &description);
+            RecoveredGuard { guard: Some(poisoned.into_inner()), fault: Some((target.to_string(), description)) }
+        }
+    }
+}
+
+// Guard returned by `lock_or_recover`; logs a recovered fault after unlocking
+pub struct RecoveredGuard<'a, T> {
+    guard: Option<MutexGuard<'a, T>>,
+    fault: Option<(String, String)>,
+}
+
+impl<T> Deref for RecoveredGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().expect("guard is only taken on drop")
+    }
+}
+
+impl<T> DerefMut for RecoveredGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().expect("guard is only taken on drop")
+    }
+}
+
+impl<T> Drop for RecoveredGuard<'_, T> {
+    fn drop(&mut self) {
+        self.guard.take();
+        if let Some((target, description)) = self.fault.take() {
+            logging::warn(&target, &format!("Fault recovered: {}", description));
+        }
+    }
+}
+
+// A mutex over shared data whose ownership is arbitrated by the Scheduler,
+// so a task holding it inherits the priority of the tasks it blocks (or runs
+// at the ceiling, depending on the protocol)
+pub struct PiMutex<T> {
+    id: u32,
+    scheduler: Arc<Mutex<Scheduler>>,
+    value: Mutex<T>,
+}
+
+impl<T> PiMutex<T> {
+    #[allow(dead_code)]
+    pub fn new(scheduler: Arc<Mutex<Scheduler>>, protocol: LockProtocol, value: T) -> Self {
+        let id = lock_or_recover(&scheduler, LOG_TARGET, "create_mutex").create_mutex(protocol);
+        PiMutex { id, scheduler, value: Mutex::new(value) }
+    }
+
+    #[allow(dead_code)]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    // Locks on behalf of the running task `task_id`. Ok(None) means the task
+    // is now blocked; once the scheduler runs it again it owns the lock and
+    // calling `lock` again returns the guard.
+    #[allow(dead_code)]
+    pub fn lock(&self, task_id: u32) -> Result<Option<PiMutexGuard<'_, T>>, SchedulerError> {
+        let outcome = lock_or_recover(&self.scheduler, LOG_TARGET, "PiMutex::lock").lock(self.id, task_id)?;
+        match outcome {
+            LockOutcome::Acquired => Ok(Some(PiMutexGuard {
+                mutex: self,
+                task_id,
+                guard: Some(lock_or_recover(&self.value, LOG_TARGET, "PiMutex::lock")),
+            })),
+            LockOutcome::Blocked => Ok(None),
+        }
+    }
+}
+
+// Unlocks the PiMutex through the Scheduler when dropped
+pub struct PiMutexGuard<'a, T> {
+    mutex: &'a PiMutex<T>,
+    task_id: u32,
+    guard: Option<RecoveredGuard<'a, T>>,
+}
+
+impl<T> Deref for PiMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().expect("guard is only taken on drop")
+    }
+}
+
+impl<T> DerefMut for PiMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().expect("guard is only taken on drop")
+    }
+}
+
+impl<T> Drop for PiMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release the data before the next owner can be scheduled
+        self.guard.take();
+        let result = lock_or_recover(&self.mutex.scheduler, LOG_TARGET, "PiMutex::unlock").unlock(self.mutex.id, self.task_id);
+        if let Err(e) = result {
+            logging::error(LOG_TARGET, &format!("Failed to unlock mutex {}: {}", self.mutex.id, e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::scheduler::Priority;
+
+    #[test]
+    fn test_lock_or_recover() {
+        let mutex = Arc::new(Mutex::new(vec![1, 2]));
+        let poisoner = Arc::clone(&mutex);
+        let _ = std::thread::spawn(move || {
+            let mut values = poisoner.lock().unwrap();
+            values.push(3);
+            panic!("panic while holding the lock");
+        }).join();
+        assert!(mutex.is_poisoned());
+
+        let values = lock_or_recover(&mutex, "sync_test", "test_lock_or_recover").clone();
+        assert_eq!(values, vec![1, 2, 3]);
+        assert!(!mutex.is_poisoned());
+        assert!(fault_events().iter().any(|fault| fault.target == "sync_test"));
+    }
+
+    // Logs into the mutex it watches, like a TopicSink logging about the topics lock
+    struct LockingSink(Arc<Mutex<Vec<String>>>);
+
+    impl logging::LogSink for LockingSink {
+        fn write(&mut self, record: &logging::LogRecord) {
+            if record.target == "sync_sink_test" {
+                self.0.lock().unwrap().push(record.message.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_recovered_fault_logged_after_unlock() {
+        let mutex = Arc::new(Mutex::new(Vec::new()));
+        let sink = logging::logger().add_sink(Box::new(LockingSink(Arc::clone(&mutex))));
+        let poisoner = Arc::clone(&mutex);
+        let _ = std::thread::spawn(move || {
+            let _values = poisoner.lock().unwrap();
+            panic!("panic while holding the lock");
+        }).join();
+
+        lock_or_recover(&mutex, "sync_sink_test", "push").push("recovered".to_string());
+        let messages = mutex.lock().unwrap().clone();
+        assert!(logging::logger().remove_sink(sink));
+        assert_eq!(messages[0], "recovered");
+        assert!(messages[1].starts_with("Fault recovered: lock poisoned before push"));
+    }
+
+    #[test]
+    fn test_pi_mutex() {
+        let scheduler = Arc::new(Mutex::new(Scheduler::new()));
+        let odometry = PiMutex::new(Arc::clone(&scheduler), LockProtocol::Inheritance, 0u32);
+        let (low, high) = {
+            let mut scheduler = scheduler.lock().unwrap();
+            let low = scheduler.add_task(1, Duration::from_secs(1));
+            let high = scheduler.add_task(3, Duration::from_secs(1));
+            (low, high)
+        };
+        let next = |scheduler: &Arc<Mutex<Scheduler>>| scheduler.lock().unwrap().get_next_task().unwrap().id;
+
+        assert_eq!(next(&scheduler), high);
+        assert_eq!(next(&scheduler), low);
+        let mut guard = odometry.lock(low).unwrap().unwrap();
+        *guard += 1;
+        assert!(odometry.lock(high).unwrap().is_none());
+        assert_eq!(scheduler.lock().unwrap().effective_priority(low), Some(Priority(3)));
+
+        drop(guard);
+        assert_eq!(scheduler.lock().unwrap().effective_priority(low), Some(Priority(1)));
+        assert_eq!(next(&scheduler), high);
+        assert_eq!(*odometry.lock(high).unwrap().unwrap(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+struct Timer<K> {
+    tick: u64,
+    at: Instant,
+    key: K,
+}
+
+// Hashed timing wheel: timers are bucketed by tick into a fixed number of
+// slots, so scheduling is O(1) and advancing only visits the elapsed slots.
+// Timers further away than one revolution stay in their slot until their tick.
+pub struct TimerWheel<K> {
+    start: Instant,
+    resolution: Duration,
+    slots: Vec<Vec<Timer<K>>>,
+    current_tick: u64,
+    len: usize,
+}
+
+impl<K> TimerWheel<K> {
+    // Ticks are counted from `start`, normally the clock's current time
+    pub fn new(resolution: Duration, slot_count: usize, start: Instant) -> Self {
+        TimerWheel {
+            start,
+            resolution: resolution.max(Duration::from_nanos(1)),
+            slots: (0..slot_count.max(1)).map(|_| Vec::new()).collect(),
+            current_tick: 0,
+            len: 0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tick_of(&self, at: Instant) -> u64 {
+        let elapsed = at.saturating_duration_since(self.start);
+        (elapsed.as_nanos() / self.resolution.as_nanos()) as u64
+    }
+
+    // Fires `key` at the first `advance` at or after `at`; timers in the past
+    // fire on the next advance
+    pub fn schedule(&mut self, at: Instant, key: K) {
+        let tick = self.tick_of(at).max(self.current_tick);
+        let slot = (tick % self.slots.len() as u64) as usize;
+        self.slots[slot].push(Timer { tick, at, key });
+        self.len += 1;
+    }
+
+    // Removes every timer for which `keep` returns false
+    pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut keep: F) {
+        for slot in self.slots.iter_mut() {
+            slot.retain(|timer| keep(&timer.key));
+        }
+        self.len = self.slots.iter().map(Vec::len).sum();
+    }
+
+    // Removes and returns every timer, in no particular order
+    pub fn drain(&mut self) -> Vec<(Instant, K)> {
+        self.len = 0;
+        self.slots.iter_mut().flat_map(|slot| slot.drain(..)).map(|timer| (timer.at, timer.key)).collect()
+    }
+
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.slots.iter().flatten().map(|timer| timer.at).min()
+    }
+
+    // Returns the timers that expired up to `now` as (deadline, key), earliest first
+    pub fn advance(&mut self, now: Instant) -> Vec<(Instant, K)> {
+        let now_tick = self.tick_of(now).max(self.current_tick);
+        let visited = (now_tick - self.current_tick + 1).min(self.slots.len() as u64);
+        let mut expired = Vec::new();
+        for offset in 0..visited {
+            let slot = ((self.current_tick + offset) % self.slots.len() as u64) as usize;
+            let timers = std::mem::take(&mut self.slots[slot]);
+            for timer in timers {
+                if timer.tick <= now_tick && timer.at <= now {
+                    expired.push((timer.at, timer.key));
+                } else {
+                    self.slots[slot].push(timer);
+                }
+            }
+        }
+        self.current_tick = now_tick;
+        self.len -= expired.len();
+        expired.sort_by_key(|&(at, _)| at);
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_wheel() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8, start);
+        wheel.schedule(start + Duration::from_millis(3), "b");
+        wheel.schedule(start + Duration::from_millis(1), "a");
+        // More than one revolution away
+        wheel.schedule(start + Duration::from_millis(20), "c");
+        assert_eq!(wheel.len(), 3);
+        assert_eq!(wheel.next_expiry(), Some(start + Duration::from_millis(1)));
+
+        let fired: Vec<&str> = wheel.advance(start + Duration::from_millis(5)).into_iter().map(|(_, key)| key).collect();
+        assert_eq!(fired, vec!["a", "b"]);
+        assert!(wheel.advance(start + Duration::from_millis(12)).is_empty());
+        assert_eq!(wheel.advance(start + Duration::from_millis(25)).len(), 1);
+        assert!(wheel.is_empty());
+
+        wheel.schedule(start, "late");
+        wheel.retain(|key| *key != "late");
+        assert!(wheel.is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+use std::any::type_name;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::codec::JsonCodec;
+use crate::ipc::{IpcError, Message, IPC};
+use crate::logging;
+use crate::sync::lock_or_recover;
+use crate::transport::{InProcessTransport, Transport, TransportError};
+
+const LOG_TARGET: &str = "topics";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicError {
+    // The topic already carries another message type
+    TypeMismatch { topic: String, expected: &'static str, found: &'static str },
+    Serialization { topic: String, message: String },
+    TopicNotFound(String),
+    AlreadySubscribed { topic: String, pid: u32 },
+    Ipc(IpcError),
+    Transport(TransportError),
+}
+
+impl fmt::Display for TopicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopicError::TypeMismatch { topic, expected, found } => {
+                write!(f, "Topic {} carries {}, not {}", topic, expected, found)
+            },
+            TopicError::Serialization { topic, message } => write!(f, "Cannot (de)serialize message on {}: {}", topic, message),
+            TopicError::TopicNotFound(topic) => write!(f, "No topic: {}", topic),
+            TopicError::AlreadySubscribed { topic, pid } => write!(f, "PID {} is already subscribed to {}", pid, topic),
+            TopicError::Ipc(e) => write!(f, "IPC error: {}", e),
+            TopicError::Transport(e) => write!(f, "Transport error: {}", e),
+        }
+    }
+}
+
+impl Error for TopicError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TopicError::Ipc(e) => Some(e),
+            TopicError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl TopicError {
+    fn from_ipc(topic: &str, e: IpcError) -> Self {
+        match e {
+            IpcError::Codec(e) => TopicError::Serialization { topic: topic.to_string(), message: e.message },
+            e => TopicError::Ipc(e),
+        }
+    }
+
+    fn from_transport(topic: &str, e: TransportError) -> Self {
+        match e {
+            TransportError::Ipc(e) => TopicError::from_ipc(topic, e),
+            e => TopicError::Transport(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicInfo {
+    pub name: String,
+    pub type_name: &'static str,
+    pub latched: bool,
+    pub publishers: Vec<u32>,
+    pub subscribers: usize,
+}
+
+struct Topic {
+    type_name: &'static str,
+    latched: bool,
+    // Last published message, replayed to new subscribers of latched topics
+    last: Option<Message>,
+    publishers: HashSet<u32>,
+    subscribers: HashSet<u32>,
+}
+
+// Named topics with typed, JSON-encoded messages, in the style of ROS
+// topics. Messages are queued in the IPC mailbox of every subscribed PID,
+// tagged with the topic name, so a process can await them like any other
+// message; the mailbox limits bound how many are kept. Publications and
+// subscriptions go through a Transport, so topics can span OS processes.
+#[derive(Clone)]
+pub struct TopicRegistry {
+    ipc: IPC,
+    transport: Arc<dyn Transport>,
+    topics: Arc<Mutex<HashMap<String, Topic>>>,
+}
+
+impl TopicRegistry {
+    // Topics within this process only
+    pub fn new(ipc: IPC) -> Self {
+        let transport = Arc::new(InProcessTransport::new(ipc.clone()));
+        Self::with_transport(ipc, transport)
+    }
+
+    // `transport` must deliver into the mailboxes of `ipc`, e.g. a UdsTransport connected with it
+    pub fn with_transport(ipc: IPC, transport: Arc<dyn Transport>) -> Self {
+        TopicRegistry { ipc, transport, topics: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    fn topic_for<'a, T>(topics: &'a mut HashMap<String, Topic>, name: &str) -> Result<&'a mut Topic, TopicError> {
+        let topic = topics.entry(name.to_string()).or_insert_with(|| Topic {
+            type_name: type_name::<T>(),
+            latched: false,
+            last: None,
+            publishers: HashSet::new(),
+            subscribers: HashSet::new(),
+        });
+        if topic.type_name != type_name::<T>() {
+            return Err(TopicError::TypeMismatch {
+                topic: name.to_string(),
+                expected: topic.type_name,
+                found: type_name::<T>(),
+            });
+        }
+        Ok(topic)
+    }
+
+    // Registers `pid` as a publisher of `name`. On a latched topic the last
+    // message is kept and delivered to every later subscriber.
+    pub fn advertise<T: Serialize>(&self, name: &str, pid: u32, latched: bool) -> Result<Publisher<T>, TopicError> {
+        let mut topics = lock_or_recover(&self.topics, LOG_TARGET, "advertise");
+        let topic = Self::topic_for::<T>(&mut topics, name)?;
+        topic.latched |= latched;
+        topic.publishers.insert(pid);
+        logging::debug(LOG_TARGET, &format!("PID {} advertised {} ({})", pid, name, topic.type_name));
+        Ok(Publisher { registry: self.clone(), topic: name.to_string(), pid, _message: PhantomData })
+    }
+
+    // Queues messages published on `name` in the existing mailbox of `pid`
+    #[allow(dead_code)]
+    pub fn subscribe<T: DeserializeOwned>(&self, name: &str, pid: u32) -> Result<Subscriber<T>, TopicError> {
+        if !self.ipc.has_mailbox(pid) {
+            return Err(TopicError::Ipc(IpcError::MailboxNotFound(pid)));
+        }
+        let mut topics = lock_or_recover(&self.topics, LOG_TARGET, "subscribe");
+        let topic = Self::topic_for::<T>(&mut topics, name)?;
+        if topic.subscribers.contains(&pid) {
+            return Err(TopicError::AlreadySubscribed { topic: name.to_string(), pid });
+        }
+        self.transport.subscribe(name, pid).map_err(|e| TopicError::from_transport(name, e))?;
+        topic.subscribers.insert(pid);
+        let latched = topic.last.clone().filter(|_| topic.latched);
+        drop(topics);
+        if let Some(last) = latched {
+            self.ipc.post(pid, last).map_err(|e| TopicError::from_ipc(name, e))?;
+        }
+        Ok(Subscriber { registry: self.clone(), topic: name.to_string(), pid, _message: PhantomData })
+    }
+
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        let topics = lock_or_recover(&self.topics, LOG_TARGET, "topics");
+        let mut infos: Vec<TopicInfo> = topics
+            .iter()
+            .map(|(name, topic)| {
+                let mut publishers: Vec<u32> = topic.publishers.iter().copied().collect();
+                publishers.sort();
+                TopicInfo {
+                    name: name.clone(),
+                    type_name: topic.type_name,
+                    latched: topic.latched,
+                    publishers,
+                    subscribers: topic.subscribers.len(),
+                }
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    // Drops every publisher and subscriber registration of `pid`; the
+    // CoreSystem calls this when the process terminates
+    pub fn remove_process(&self, pid: u32) {
+        for (name, topic) in lock_or_recover(&self.topics, LOG_TARGET, "remove_process").iter_mut() {
+            topic.publishers.remove(&pid);
+            if topic.subscribers.remove(&pid) {
+                self.transport.unsubscribe(name, pid);
+            }
+        }
+    }
+
+    fn publish(&self, name: &str, message: Message) -> Result<usize, TopicError> {
+        let mut topics = lock_or_recover(&self.topics, LOG_TARGET, "publish");
+        let topic = topics.get_mut(name).ok_or_else(|| TopicError::TopicNotFound(name.to_string()))?;
+        if topic.latched {
+            topic.last = Some(message.clone());
+        }
+        drop(topics);
+        self.transport.publish(name, message).map_err(|e| TopicError::from_transport(name, e))
+    }
+
+    fn unsubscribe(&self, name: &str, pid: u32) {
+        if let Some(topic) = lock_or_recover(&self.topics, LOG_TARGET, "unsubscribe").get_mut(name) {
+            if topic.subscribers.remove(&pid) {
+                self.transport.unsubscribe(name, pid);
+            }
+        }
+    }
+}
+
+pub struct Publisher<T> {
+    registry: TopicRegistry,
+    topic: String,
+    pid: u32,
+    _message: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> Publisher<T> {
+    #[allow(dead_code)]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    // Returns the number of subscribers the message was queued for
+    pub fn publish(&self, message: &T) -> Result<usize, TopicError> {
+        let mut message = Message::encode::<T, JsonCodec>(self.pid, "", message).map_err(|e| TopicError::from_ipc(&self.topic, e))?;
+        message.header.topic = self.topic.clone();
+        self.registry.publish(&self.topic, message)
+    }
+}
+
+// Unsubscribes when dropped
+pub struct Subscriber<T> {
+    registry: TopicRegistry,
+    topic: String,
+    pid: u32,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Subscriber<T> {
+    #[allow(dead_code)]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    // The next message on this topic and the PID that published it, if any.
+    // Other messages in the mailbox are left in place.
+    pub fn try_recv(&self) -> Result<Option<(u32, T)>, TopicError> {
+        let ipc = &self.registry.ipc;
+        if !ipc.has_mailbox(self.pid) {
+            return Err(TopicError::Ipc(IpcError::MailboxNotFound(self.pid)));
+        }
+        let Some(message) = ipc.take_message(self.pid, |message| message.header.topic == self.topic) else {
+            return Ok(None);
+        };
+        let value = message.decode::<T, JsonCodec>().map_err(|e| TopicError::from_ipc(&self.topic, e))?;
+        Ok(Some((message.sender, value)))
+    }
+
+    // Waits for the next message on this topic. A process body awaiting this
+    // is parked by the CoreSystem like one waiting in `IPC::recv_async`.
+    #[allow(dead_code)]
+    pub async fn recv(&self) -> Result<(u32, T), TopicError> {
+        loop {
+            // Taken before looking, so a message published in between still wakes us
+            let delivery = self.registry.ipc.next_delivery(self.pid);
+            if let Some(received) = self.try_recv()? {
+                return Ok(received);
+            }
+            delivery.await.map_err(TopicError::Ipc)?;
+        }
+    }
+
+    // Messages the subscriber's mailbox discarded because it was full,
+    // including messages that were not published on this topic
+    #[allow(dead_code)]
+    pub fn dropped(&self) -> u64 {
+        self.registry.ipc.mailbox_stats(self.pid).map_or(0, |stats| stats.dropped)
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        self.registry.unsubscribe(&self.topic, self.pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use crate::core_system::{CoreSystem, ProcessState, DEFAULT_PRIORITY};
+    use crate::ipc::OverflowPolicy;
+    use crate::memory_manager::MemoryManager;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Range {
+        sensor: String,
+        meters: f64,
+    }
+
+    #[test]
+    fn test_publish_subscribe() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(3);
+        ipc.create_mailbox(4);
+        ipc.set_limits(3, 2, OverflowPolicy::DropOldest).unwrap();
+        let registry = TopicRegistry::new(ipc.clone());
+        let front = registry.advertise::<Range>("/sensors/range", 1, false).unwrap();
+        let rear = registry.advertise::<Range>("/sensors/range", 2, false).unwrap();
+        let planner = registry.subscribe::<Range>("/sensors/range", 3).unwrap();
+        let logger = registry.subscribe::<Range>("/sensors/range", 4).unwrap();
+
+        let reading = Range { sensor: "front".to_string(), meters: 1.5 };
+        assert_eq!(front.publish(&reading).unwrap(), 2);
+        rear.publish(&Range { sensor: "rear".to_string(), meters: 0.4 }).unwrap();
+        rear.publish(&Range { sensor: "rear".to_string(), meters: 0.3 }).unwrap();
+
+        // The planner's mailbox holds two messages, so the oldest was dropped
+        assert_eq!(planner.dropped(), 1);
+        assert_eq!(planner.try_recv().unwrap().unwrap().1.meters, 0.4);
+        assert_eq!(logger.try_recv().unwrap(), Some((1, reading)));
+
+        // Direct messages in the same mailbox are left for the process
+        ipc.send_message(9, 4, "direct".to_string()).unwrap();
+        assert_eq!(logger.try_recv().unwrap().unwrap().1.meters, 0.4);
+        assert_eq!(logger.try_recv().unwrap().unwrap().1.meters, 0.3);
+        assert_eq!(logger.try_recv().unwrap(), None);
+        assert_eq!(ipc.receive_message(4).unwrap().content, "direct");
+
+        assert!(matches!(
+            registry.subscribe::<String>("/sensors/range", 4),
+            Err(TopicError::TypeMismatch { .. })
+        ));
+        assert!(matches!(registry.subscribe::<Range>("/sensors/range", 4), Err(TopicError::AlreadySubscribed { .. })));
+        assert!(matches!(registry.subscribe::<Range>("/sensors/range", 5), Err(TopicError::Ipc(IpcError::MailboxNotFound(5)))));
+        drop(planner);
+        let info = &registry.topics()[0];
+        assert_eq!(info.publishers, vec![1, 2]);
+        assert_eq!(info.subscribers, 1);
+    }
+
+    #[test]
+    fn test_latched_topic() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(8);
+        let registry = TopicRegistry::new(ipc);
+        let map = registry.advertise::<Vec<u8>>("/map", 7, true).unwrap();
+        assert_eq!(map.publish(&vec![1, 2]).unwrap(), 0);
+        map.publish(&vec![3, 4]).unwrap();
+
+        // A late subscriber gets the last value only
+        let late = registry.subscribe::<Vec<u8>>("/map", 8).unwrap();
+        assert_eq!(late.try_recv().unwrap(), Some((7, vec![3, 4])));
+        assert_eq!(late.try_recv().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_process_waits_on_topic() {
+        let ipc = IPC::new();
+        let registry = TopicRegistry::new(ipc.clone());
+        let mut core_system = CoreSystem::with_resources(ipc.clone(), Arc::new(Mutex::new(MemoryManager::new(1024))));
+        core_system.set_topics(registry.clone());
+        let publisher = registry.advertise::<f64>("/sensors/distance", 0, false).unwrap();
+
+        let subscriptions = registry.clone();
+        let pid = core_system.spawn("listener", None, DEFAULT_PRIORITY, async move {
+            let distance = subscriptions.subscribe::<f64>("/sensors/distance", 1).unwrap();
+            let _echo = subscriptions.advertise::<f64>("/echo", 1, false).unwrap();
+            let (_, meters) = distance.recv().await.unwrap();
+            meters as i32
+        }).unwrap();
+        assert_eq!(pid, 1);
+        let waiter = core_system.wait(pid);
+
+        core_system.step().unwrap();
+        assert_eq!(core_system.process_state(pid), Some(ProcessState::Blocked));
+        assert_eq!(core_system.process_table().unwrap()[0].blocked_on.as_deref(), Some("message"));
+        assert_eq!(registry.topics()[1].subscribers, 1);
+
+        publisher.publish(&4.5).unwrap();
+        core_system.step().unwrap();
+        assert_eq!(waiter.await.unwrap(), 4);
+
+        // The terminated process no longer publishes or subscribes
+        let topics = registry.topics();
+        assert!(topics[0].publishers.is_empty());
+        assert_eq!(topics[1].subscribers, 0);
+    }
+}
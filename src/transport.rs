@@ -0,0 +1,699 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+use crate::ipc::{IpcError, Message, MessageHeader, IPC};
+use crate::logging;
+use crate::sync::lock_or_recover;
+
+const LOG_TARGET: &str = "transport";
+
+// Frames queued per connection before senders see backpressure
+pub const FRAME_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportError {
+    Ipc(IpcError),
+    Io { operation: &'static str, message: String },
+    // Shared-memory buffers only exist inside one OS process
+    NotTransferable,
+    Disconnected,
+    // The outgoing queue is full; the broker or a receiver is not keeping up
+    QueueFull,
+    Rejected(String),
+    AddressInUse(PathBuf),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransportError::Ipc(e) => write!(f, "IPC error: {}", e),
+            TransportError::Io { operation, message } => write!(f, "Socket {} failed: {}", operation, message),
+            TransportError::NotTransferable => write!(f, "Shared-memory buffers cannot leave the process"),
+            TransportError::Disconnected => write!(f, "Connection to the broker was closed"),
+            TransportError::QueueFull => write!(f, "Outgoing frame queue is full"),
+            TransportError::Rejected(message) => write!(f, "Broker rejected the request: {}", message),
+            TransportError::AddressInUse(path) => write!(f, "A broker is already listening on {}", path.display()),
+        }
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransportError::Ipc(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<IpcError> for TransportError {
+    fn from(e: IpcError) -> Self {
+        TransportError::Ipc(e)
+    }
+}
+
+fn io_error(operation: &'static str) -> impl Fn(std::io::Error) -> TransportError {
+    move |e| TransportError::Io { operation, message: e.to_string() }
+}
+
+fn answer_error(answer: Result<Answer, oneshot::error::RecvError>) -> Result<(), TransportError> {
+    match answer {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err((NackReason::QueueFull, _))) => Err(TransportError::QueueFull),
+        Ok(Err((_, message))) => Err(TransportError::Rejected(message)),
+        Err(_) => Err(TransportError::Disconnected),
+    }
+}
+
+fn queue_error<T>(e: mpsc::error::TrySendError<T>) -> TransportError {
+    match e {
+        mpsc::error::TrySendError::Full(_) => TransportError::QueueFull,
+        mpsc::error::TrySendError::Closed(_) => TransportError::Disconnected,
+    }
+}
+
+// Resolves once the recipient's endpoint has accepted the message
+pub type Sending<'a> = Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>>;
+
+// Moves messages to a mailbox by PID, or to every mailbox subscribed to a topic
+pub trait Transport: Send + Sync {
+    // Fails with MailboxNotFound when no endpoint hosts `recipient`
+    #[allow(dead_code)]
+    fn send(&self, recipient: u32, message: Message) -> Sending<'_>;
+
+    // Returns the number of subscribers in this process the message was queued for
+    fn publish(&self, topic: &str, message: Message) -> Result<usize, TransportError>;
+
+    // Queues messages published on `topic` in the mailbox of `pid`
+    fn subscribe(&self, topic: &str, pid: u32) -> Result<(), TransportError>;
+
+    fn unsubscribe(&self, topic: &str, pid: u32);
+}
+
+// The mailboxes of one IPC instance
+#[derive(Clone)]
+pub struct InProcessTransport {
+    ipc: IPC,
+    topics: Arc<Mutex<HashMap<String, HashSet<u32>>>>,
+}
+
+impl InProcessTransport {
+    pub fn new(ipc: IPC) -> Self {
+        InProcessTransport { ipc, topics: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    #[allow(dead_code)]
+    pub fn ipc(&self) -> &IPC {
+        &self.ipc
+    }
+
+    fn has_subscribers(&self, topic: &str) -> bool {
+        lock_or_recover(&self.topics, LOG_TARGET, "has_subscribers")
+            .get(topic)
+            .is_some_and(|pids| !pids.is_empty())
+    }
+
+    fn deliver_topic(&self, topic: &str, message: &Message) -> usize {
+        let mut subscribers: Vec<u32> = lock_or_recover(&self.topics, LOG_TARGET, "publish")
+            .get(topic)
+            .map(|pids| pids.iter().copied().collect())
+            .unwrap_or_default();
+        subscribers.sort();
+        let mut delivered = 0;
+        for pid in subscribers {
+            match self.ipc.post(pid, message.clone()) {
+                Ok(()) => delivered += 1,
+                Err(e) => logging::warn(LOG_TARGET, &format!("Dropping {} message for PID {}: {}", topic, pid, e)),
+            }
+        }
+        delivered
+    }
+}
+
+impl Transport for InProcessTransport {
+    fn send(&self, recipient: u32, message: Message) -> Sending<'_> {
+        let posted = self.ipc.post(recipient, message).map_err(TransportError::from);
+        Box::pin(std::future::ready(posted))
+    }
+
+    fn publish(&self, topic: &str, message: Message) -> Result<usize, TransportError> {
+        Ok(self.deliver_topic(topic, &message))
+    }
+
+    fn subscribe(&self, topic: &str, pid: u32) -> Result<(), TransportError> {
+        lock_or_recover(&self.topics, LOG_TARGET, "subscribe")
+            .entry(topic.to_string())
+            .or_default()
+            .insert(pid);
+        Ok(())
+    }
+
+    fn unsubscribe(&self, topic: &str, pid: u32) {
+        if let Some(subscribers) = lock_or_recover(&self.topics, LOG_TARGET, "unsubscribe").get_mut(topic) {
+            subscribers.remove(&pid);
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct WireMessage {
+    sender: u32,
+    header: MessageHeader,
+    content: String,
+    payload: Vec<u8>,
+}
+
+impl WireMessage {
+    fn from_message(message: Message) -> Result<Self, TransportError> {
+        if message.buffer.is_some() {
+            return Err(TransportError::NotTransferable);
+        }
+        Ok(WireMessage {
+            sender: message.sender,
+            header: message.header,
+            content: message.content,
+            payload: message.payload,
+        })
+    }
+
+    fn into_message(self) -> Message {
+        Message {
+            sender: self.sender,
+            header: self.header,
+            content: self.content,
+            payload: self.payload,
+            buffer: None,
+        }
+    }
+}
+
+// Socket protocol: one JSON object per line, tagged by "op", e.g.
+// {"op":"register","pids":[7]}. Register, subscribe, unsubscribe, send and
+// ping are answered with {"op":"ack"} or {"op":"nack","reason":...,"message":...},
+// so a tool in another language only needs a JSON library.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Frame {
+    // Client to broker
+    Register { pids: Vec<u32> },
+    Subscribe { topic: String },
+    Unsubscribe { topic: String },
+    Ping,
+    Send { recipient: u32, message: WireMessage },
+    Publish { topic: String, message: WireMessage },
+    // Broker to client
+    Ack,
+    Nack { reason: NackReason, message: String },
+    Deliver { recipient: u32, message: WireMessage },
+    DeliverTopic { topic: String, message: WireMessage },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NackReason {
+    // A PID in a register request belongs to another connection
+    Claimed,
+    // No connection hosts the recipient of a send
+    NoEndpoint,
+    // The recipient's connection is not keeping up
+    QueueFull,
+}
+
+async fn write_frames(mut writer: OwnedWriteHalf, mut frames: mpsc::Receiver<Frame>) {
+    while let Some(frame) = frames.recv().await {
+        let mut line = match serde_json::to_string(&frame) {
+            Ok(line) => line,
+            Err(e) => {
+                logging::error(LOG_TARGET, &format!("Cannot encode frame: {}", e));
+                continue;
+            },
+        };
+        line.push('\n');
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            logging::debug(LOG_TARGET, &format!("Connection closed while writing: {}", e));
+            return;
+        }
+    }
+}
+
+#[derive(Default)]
+struct BrokerState {
+    connections: HashMap<u64, mpsc::Sender<Frame>>,
+    // Connection that claimed each PID
+    pids: HashMap<u32, u64>,
+    topics: HashMap<String, HashSet<u64>>,
+}
+
+// Routes messages between the processes connected to a Unix domain socket.
+// Every connection claims the PIDs whose mailboxes it hosts and the topics
+// it wants; the broker forwards by PID or fans out by topic.
+pub struct Broker {
+    path: PathBuf,
+    accept: JoinHandle<()>,
+}
+
+impl Broker {
+    // Listens on `path`, replacing a stale socket file. Fails if another
+    // broker still answers there or the path is not a socket. Must be called
+    // from within a tokio runtime.
+    #[allow(dead_code)]
+    pub fn start(path: impl AsRef<Path>) -> Result<Self, TransportError> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(TransportError::Io { operation: "bind", message: format!("{} is not a socket", path.display()) });
+            }
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(TransportError::AddressInUse(path));
+            }
+            std::fs::remove_file(&path).map_err(io_error("remove stale socket"))?;
+        }
+        let listener = UnixListener::bind(&path).map_err(io_error("bind"))?;
+        let state = Arc::new(Mutex::new(BrokerState::default()));
+        logging::info(LOG_TARGET, &format!("Broker listening on {}", path.display()));
+
+        let accept = tokio::spawn(async move {
+            // Owned here, so stopping the broker also closes every connection
+            let mut connections = JoinSet::new();
+            let mut next_id = 1;
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            connections.spawn(serve(Arc::clone(&state), next_id, stream));
+                            next_id += 1;
+                        },
+                        Err(e) => {
+                            logging::error(LOG_TARGET, &format!("Broker stopped accepting: {}", e));
+                            return;
+                        },
+                    },
+                    Some(_) = connections.join_next() => {},
+                }
+            }
+        });
+        Ok(Broker { path, accept })
+    }
+
+    #[allow(dead_code)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.accept.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn serve(state: Arc<Mutex<BrokerState>>, id: u64, stream: UnixStream) {
+    let (reader, writer) = stream.into_split();
+    let (outgoing, frames) = mpsc::channel(FRAME_QUEUE_CAPACITY);
+    lock_or_recover(&state, LOG_TARGET, "connect").connections.insert(id, outgoing.clone());
+    let writer = tokio::spawn(write_frames(writer, frames));
+
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                let reply = match serde_json::from_str::<Frame>(&line) {
+                    Ok(frame) => route(&state, id, frame),
+                    Err(e) => Some(Frame::Error { message: format!("Bad frame: {}", e) }),
+                };
+                // Only this connection's own queue is awaited, so a client
+                // that stops reading slows down nobody but itself
+                if let Some(reply) = reply {
+                    let _ = outgoing.send(reply).await;
+                }
+            },
+            Ok(None) => break,
+            Err(e) => {
+                logging::warn(LOG_TARGET, &format!("Connection {} failed: {}", id, e));
+                break;
+            },
+        }
+    }
+
+    {
+        let mut broker = lock_or_recover(&state, LOG_TARGET, "disconnect");
+        broker.connections.remove(&id);
+        broker.pids.retain(|_, connection| *connection != id);
+        for subscribers in broker.topics.values_mut() {
+            subscribers.remove(&id);
+        }
+    }
+    drop(outgoing);
+    let _ = writer.await;
+}
+
+fn nack(reason: NackReason, message: String) -> Option<Frame> {
+    Some(Frame::Nack { reason, message })
+}
+
+// Queues deliveries to other connections without waiting for room, and
+// returns the answer for the connection the frame came from
+fn route(state: &Mutex<BrokerState>, from: u64, frame: Frame) -> Option<Frame> {
+    let mut state = lock_or_recover(state, LOG_TARGET, "route");
+    match frame {
+        Frame::Register { pids } => {
+            let mut taken: Vec<u32> = pids
+                .iter()
+                .copied()
+                .filter(|pid| state.pids.get(pid).is_some_and(|&connection| connection != from))
+                .collect();
+            taken.sort();
+            taken.dedup();
+            if !taken.is_empty() {
+                return nack(NackReason::Claimed, format!("PIDs {:?} are claimed by another connection", taken));
+            }
+            for pid in pids {
+                state.pids.insert(pid, from);
+            }
+            Some(Frame::Ack)
+        },
+        Frame::Subscribe { topic } => {
+            state.topics.entry(topic).or_default().insert(from);
+            Some(Frame::Ack)
+        },
+        Frame::Unsubscribe { topic } => {
+            if let Some(subscribers) = state.topics.get_mut(&topic) {
+                subscribers.remove(&from);
+                if subscribers.is_empty() {
+                    state.topics.remove(&topic);
+                }
+            }
+            Some(Frame::Ack)
+        },
+        Frame::Ping => Some(Frame::Ack),
+        Frame::Send { recipient, message } => {
+            let Some(connection) = state.pids.get(&recipient).and_then(|connection| state.connections.get(connection)) else {
+                return nack(NackReason::NoEndpoint, format!("No endpoint for PID {}", recipient));
+            };
+            match connection.try_send(Frame::Deliver { recipient, message }) {
+                Ok(()) => Some(Frame::Ack),
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    nack(NackReason::QueueFull, format!("The endpoint for PID {} is not keeping up", recipient))
+                },
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    nack(NackReason::NoEndpoint, format!("The endpoint for PID {} has disconnected", recipient))
+                },
+            }
+        },
+        Frame::Publish { topic, message } => {
+            // The publisher has already delivered to its own subscribers
+            let subscribers = state.topics.get(&topic).into_iter().flatten().filter(|&&connection| connection != from);
+            for connection in subscribers {
+                let Some(subscriber) = state.connections.get(connection) else { continue };
+                let frame = Frame::DeliverTopic { topic: topic.clone(), message: message.clone() };
+                if let Err(mpsc::error::TrySendError::Full(_)) = subscriber.try_send(frame) {
+                    logging::warn(LOG_TARGET, &format!("Connection {} is not keeping up, dropping a message on {}", connection, topic));
+                }
+            }
+            None
+        },
+        Frame::Ack | Frame::Nack { .. } | Frame::Deliver { .. } | Frame::DeliverTopic { .. } | Frame::Error { .. } => {
+            Some(Frame::Error { message: "Unexpected frame from a client".to_string() })
+        },
+    }
+}
+
+// The broker's answer to a request: Ack, or the reason it was refused
+type Answer = Result<(), (NackReason, String)>;
+
+// Callers waiting for the broker to answer a request, in send order
+type Answers = Arc<Mutex<VecDeque<oneshot::Sender<Answer>>>>;
+
+// Connects one IPC instance to a broker. Messages for mailboxes that exist
+// locally stay in-process; everything else goes through the broker.
+// Sends that would overflow the bounded outgoing queue fail with QueueFull
+// rather than buffering without limit.
+pub struct UdsTransport {
+    local: InProcessTransport,
+    outgoing: mpsc::Sender<Frame>,
+    answers: Answers,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+}
+
+impl UdsTransport {
+    // Claims `pids` at the broker; messages routed to them are queued in the
+    // mailboxes of `ipc`. Fails with Rejected if another connection holds one of them.
+    #[allow(dead_code)]
+    pub async fn connect(path: impl AsRef<Path>, ipc: IPC, pids: &[u32]) -> Result<Self, TransportError> {
+        let stream = UnixStream::connect(path).await.map_err(io_error("connect"))?;
+        let (reader, writer) = stream.into_split();
+        let (outgoing, frames) = mpsc::channel(FRAME_QUEUE_CAPACITY);
+        let local = InProcessTransport::new(ipc);
+        let answers = Arc::new(Mutex::new(VecDeque::new()));
+        let writer = tokio::spawn(write_frames(writer, frames));
+        let reader = tokio::spawn(read_deliveries(reader, local.clone(), Arc::clone(&answers)));
+
+        let transport = UdsTransport { local, outgoing, answers, reader, writer };
+        transport.request(Frame::Register { pids: pids.to_vec() }).await?;
+        Ok(transport)
+    }
+
+    #[allow(dead_code)]
+    pub fn local(&self) -> &InProcessTransport {
+        &self.local
+    }
+
+    // False once either direction of the broker connection has shut down
+    #[allow(dead_code)]
+    pub fn is_connected(&self) -> bool {
+        !self.reader.is_finished() && !self.writer.is_finished()
+    }
+
+    // Waits until the broker has handled every frame sent before, e.g. so a
+    // subscription is in place before another process publishes
+    #[allow(dead_code)]
+    pub async fn sync(&self) -> Result<(), TransportError> {
+        self.request(Frame::Ping).await
+    }
+
+    // Queues the frame and its answer waiter together, so answers stay in order
+    fn queue_request(&self, permit: mpsc::Permit<'_, Frame>, frame: Frame) -> oneshot::Receiver<Answer> {
+        let (done, answer) = oneshot::channel();
+        let mut answers = lock_or_recover(&self.answers, LOG_TARGET, "request");
+        permit.send(frame);
+        answers.push_back(done);
+        answer
+    }
+
+    async fn request(&self, frame: Frame) -> Result<(), TransportError> {
+        let permit = self.outgoing.reserve().await.map_err(|_| TransportError::Disconnected)?;
+        answer_error(self.queue_request(permit, frame).await)
+    }
+}
+
+impl Transport for UdsTransport {
+    // Waits for the broker to hand the message to the recipient's connection
+    fn send(&self, recipient: u32, message: Message) -> Sending<'_> {
+        if self.local.ipc.has_mailbox(recipient) {
+            return self.local.send(recipient, message);
+        }
+        Box::pin(async move {
+            let message = WireMessage::from_message(message)?;
+            let permit = self.outgoing.try_reserve().map_err(queue_error)?;
+            match self.queue_request(permit, Frame::Send { recipient, message }).await {
+                Ok(Err((NackReason::NoEndpoint, _))) => Err(TransportError::Ipc(IpcError::MailboxNotFound(recipient))),
+                answer => answer_error(answer),
+            }
+        })
+    }
+
+    fn publish(&self, topic: &str, message: Message) -> Result<usize, TransportError> {
+        let wire = WireMessage::from_message(message.clone())?;
+        // Reserved first, so a full queue does not leave a half-delivered message
+        let permit = self.outgoing.try_reserve().map_err(queue_error)?;
+        let delivered = self.local.deliver_topic(topic, &message);
+        permit.send(Frame::Publish { topic: topic.to_string(), message: wire });
+        Ok(delivered)
+    }
+
+    // Subscribes here and at the broker, so publishers in other processes
+    // reach `pid` too. The broker's answer is not awaited; see `sync`.
+    fn subscribe(&self, topic: &str, pid: u32) -> Result<(), TransportError> {
+        let permit = self.outgoing.try_reserve().map_err(queue_error)?;
+        self.local.subscribe(topic, pid)?;
+        drop(self.queue_request(permit, Frame::Subscribe { topic: topic.to_string() }));
+        Ok(())
+    }
+
+    // Tells the broker to stop forwarding the topic once no local
+    // subscriber is left
+    fn unsubscribe(&self, topic: &str, pid: u32) {
+        self.local.unsubscribe(topic, pid);
+        if self.local.has_subscribers(topic) {
+            return;
+        }
+        match self.outgoing.try_reserve() {
+            Ok(permit) => drop(self.queue_request(permit, Frame::Unsubscribe { topic: topic.to_string() })),
+            Err(e) => logging::warn(LOG_TARGET, &format!("Cannot unsubscribe from {} at the broker: {}", topic, queue_error(e))),
+        }
+    }
+}
+
+impl Drop for UdsTransport {
+    fn drop(&mut self) {
+        // The writer flushes queued frames and exits once `outgoing` is dropped
+        self.reader.abort();
+    }
+}
+
+async fn read_deliveries(reader: OwnedReadHalf, local: InProcessTransport, answers: Answers) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match serde_json::from_str::<Frame>(&line) {
+            Ok(Frame::Ack) => {
+                if let Some(done) = lock_or_recover(&answers, LOG_TARGET, "ack").pop_front() {
+                    let _ = done.send(Ok(()));
+                }
+            },
+            Ok(Frame::Nack { reason, message }) => {
+                if let Some(done) = lock_or_recover(&answers, LOG_TARGET, "nack").pop_front() {
+                    let _ = done.send(Err((reason, message)));
+                }
+            },
+            Ok(Frame::Deliver { recipient, message }) => {
+                if let Err(e) = local.ipc.post(recipient, message.into_message()) {
+                    logging::warn(LOG_TARGET, &format!("Dropping message for PID {}: {}", recipient, e));
+                }
+            },
+            Ok(Frame::DeliverTopic { topic, message }) => {
+                local.deliver_topic(&topic, &message.into_message());
+            },
+            Ok(Frame::Error { message }) => logging::warn(LOG_TARGET, &format!("Broker: {}", message)),
+            Ok(_) => logging::warn(LOG_TARGET, "Unexpected frame from the broker"),
+            Err(e) => logging::warn(LOG_TARGET, &format!("Bad frame from the broker: {}", e)),
+        }
+    }
+    logging::info(LOG_TARGET, "Disconnected from the broker");
+    // Fails pending requests
+    lock_or_recover(&answers, LOG_TARGET, "disconnect").clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::codec::BincodeCodec;
+    use crate::memory_manager::MemoryManager;
+    use crate::shared_memory::SharedMemory;
+    use crate::topics::TopicRegistry;
+
+    #[tokio::test]
+    async fn test_in_process_topics() {
+        let ipc = IPC::new();
+        ipc.create_mailbox(1);
+        ipc.create_mailbox(2);
+        let transport = InProcessTransport::new(ipc.clone());
+        transport.subscribe("/cmd_vel", 1).unwrap();
+        transport.subscribe("/cmd_vel", 2).unwrap();
+        transport.unsubscribe("/cmd_vel", 2);
+
+        assert_eq!(transport.publish("/cmd_vel", Message::text(9, "0.5".to_string())), Ok(1));
+        assert_eq!(ipc.receive_message(1).unwrap().content, "0.5");
+        assert!(ipc.receive_message(2).is_none());
+        assert_eq!(
+            transport.send(3, Message::text(9, "lost".to_string())).await,
+            Err(TransportError::Ipc(IpcError::MailboxNotFound(3)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_broker() {
+        let path = std::env::temp_dir().join(format!("metaros-broker-{}.sock", std::process::id()));
+        // A socket file left behind by a dead broker is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let broker = Broker::start(&path).unwrap();
+        assert_eq!(Broker::start(&path).err(), Some(TransportError::AddressInUse(path.clone())));
+        let timeout = Duration::from_secs(5);
+
+        // Two IPC instances stand in for two OS processes
+        let planner_ipc = IPC::new();
+        planner_ipc.create_mailbox(1);
+        let driver_ipc = IPC::new();
+        driver_ipc.create_mailbox(2);
+        driver_ipc.create_mailbox(3);
+        let planner = Arc::new(UdsTransport::connect(broker.path(), planner_ipc.clone(), &[1]).await.unwrap());
+        let driver = Arc::new(UdsTransport::connect(broker.path(), driver_ipc.clone(), &[2, 3]).await.unwrap());
+        driver.subscribe("/sensors/range", 3).unwrap();
+        driver.sync().await.unwrap();
+        assert!(matches!(
+            UdsTransport::connect(broker.path(), IPC::new(), &[4, 2]).await,
+            Err(TransportError::Rejected(_))
+        ));
+
+        let scan = vec![1.0f32, 2.5];
+        planner.send(2, Message::encode::<Vec<f32>, BincodeCodec>(1, "lidar", &scan).unwrap()).await.unwrap();
+        let received = driver_ipc.recv_timeout(2, timeout).await.unwrap();
+        assert_eq!((received.sender, received.header.frame_id.as_str()), (1, "lidar"));
+        assert_eq!(received.decode::<Vec<f32>, BincodeCodec>().unwrap(), scan);
+
+        assert_eq!(planner.publish("/sensors/range", Message::text(1, "0.4".to_string())), Ok(0));
+        assert_eq!(driver_ipc.recv_timeout(3, timeout).await.unwrap().content, "0.4");
+        driver.send(1, Message::text(2, "done".to_string())).await.unwrap();
+        assert_eq!(
+            driver.send(99, Message::text(2, "lost".to_string())).await,
+            Err(TransportError::Ipc(IpcError::MailboxNotFound(99)))
+        );
+        assert_eq!(planner_ipc.recv_timeout(1, timeout).await.unwrap().content, "done");
+
+        // Typed topics work across the broker too
+        let driver_topics = TopicRegistry::with_transport(driver_ipc.clone(), driver.clone());
+        let scans = driver_topics.subscribe::<Vec<f32>>("/scan", 2).unwrap();
+        driver.sync().await.unwrap();
+        let planner_topics = TopicRegistry::with_transport(planner_ipc.clone(), planner.clone());
+        planner_topics.advertise::<Vec<f32>>("/scan", 1, false).unwrap().publish(&scan).unwrap();
+        assert_eq!(tokio::time::timeout(timeout, scans.recv()).await.unwrap().unwrap(), (1, scan.clone()));
+        assert!(driver.is_connected());
+
+        let memory = SharedMemory::new(Arc::new(Mutex::new(MemoryManager::new(64))));
+        let frame = memory.loan(16).unwrap().publish();
+        assert_eq!(planner.send(2, Message::shared(1, "camera", frame)).await, Err(TransportError::NotTransferable));
+
+        // A client written in another language speaks the same line protocol
+        let mut tool = BufReader::new(UnixStream::connect(broker.path()).await.unwrap());
+        tool.get_mut().write_all(b"{\"op\":\"register\",\"pids\":[7]}\n").await.unwrap();
+        let mut line = String::new();
+        tool.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim(), "{\"op\":\"ack\"}");
+        planner.send(7, Message::text(1, "hello tool".to_string())).await.unwrap();
+        line.clear();
+        tool.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("{\"op\":\"deliver\",\"recipient\":7,"));
+        assert!(line.contains("\"content\":\"hello tool\""));
+
+        // After unsubscribing, the broker stops forwarding the topic
+        for request in ["{\"op\":\"subscribe\",\"topic\":\"/cmd\"}\n", "{\"op\":\"unsubscribe\",\"topic\":\"/cmd\"}\n"] {
+            tool.get_mut().write_all(request.as_bytes()).await.unwrap();
+            line.clear();
+            tool.read_line(&mut line).await.unwrap();
+            assert_eq!(line.trim(), "{\"op\":\"ack\"}");
+        }
+        planner.publish("/cmd", Message::text(1, "stop".to_string())).unwrap();
+        planner.sync().await.unwrap();
+        tool.get_mut().write_all(b"{\"op\":\"ping\"}\n").await.unwrap();
+        line.clear();
+        tool.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim(), "{\"op\":\"ack\"}");
+
+        drop(broker);
+        assert!(!path.exists());
+    }
+}
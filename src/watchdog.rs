@@ -0,0 +1,249 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::clock::{self, SharedClock};
+use crate::core_system::CoreSystem;
+use crate::hal::HAL;
+use crate::ipc::IPC;
+use crate::logging;
+use crate::sync::lock_or_recover;
+
+const LOG_TARGET: &str = "watchdog";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchdogAction {
+    // Restart the registered process through `CoreSystem::restart_process`
+    #[allow(dead_code)]
+    Restart,
+    // Bring all actuators to a safe state
+    SafeStop,
+    // Safe-stop and ask the caller to shut the system down
+    Halt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogExpiry {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub action: WatchdogAction,
+    // Time since the last kick when the expiry was detected
+    pub overdue: Duration,
+}
+
+// Handed to the watched process, which must call `kick` at least once per timeout
+#[derive(Clone)]
+pub struct WatchdogHandle {
+    last_kick: Arc<Mutex<Instant>>,
+    // The watchdog's clock, so a kick is measured against the same time base as `check`
+    clock: Arc<Mutex<SharedClock>>,
+}
+
+impl WatchdogHandle {
+    pub fn kick(&self) {
+        let now = lock_or_recover(&self.clock, LOG_TARGET, "kick").now();
+        *lock_or_recover(&self.last_kick, LOG_TARGET, "kick") = now;
+    }
+
+    fn last_kick(&self) -> Instant {
+        *lock_or_recover(&self.last_kick, LOG_TARGET, "check")
+    }
+}
+
+struct Entry {
+    name: String,
+    pid: Option<u32>,
+    timeout: Duration,
+    action: WatchdogAction,
+    handle: WatchdogHandle,
+    // The kick that was current when the entry last expired, so each missed
+    // deadline fires once
+    expired_at_kick: Option<Instant>,
+}
+
+pub struct Watchdog {
+    ipc: IPC,
+    mailbox: u32,
+    entries: Vec<Entry>,
+    clock: Arc<Mutex<SharedClock>>,
+}
+
+impl Watchdog {
+    // Expiries are reported as messages to `mailbox`
+    pub fn new(ipc: IPC, mailbox: u32) -> Self {
+        Watchdog { ipc, mailbox, entries: Vec::new(), clock: Arc::new(Mutex::new(clock::system_clock())) }
+    }
+
+    // Kicks from the old clock cannot be compared with the new one, so every
+    // registered entry starts a fresh timeout
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        *lock_or_recover(&self.clock, LOG_TARGET, "set_clock") = clock;
+        for entry in self.entries.iter_mut() {
+            entry.handle.kick();
+            entry.expired_at_kick = None;
+        }
+    }
+
+    fn now(&self) -> Instant {
+        lock_or_recover(&self.clock, LOG_TARGET, "now").now()
+    }
+
+    // Starts watching `name`. `pid` is the process restarted by `WatchdogAction::Restart`;
+    // use None for non-process activities such as the main loop.
+    pub fn register(&mut self, name: &str, pid: Option<u32>, timeout: Duration, action: WatchdogAction) -> WatchdogHandle {
+        let handle = WatchdogHandle { last_kick: Arc::new(Mutex::new(self.now())), clock: Arc::clone(&self.clock) };
+        self.entries.push(Entry {
+            name: name.to_string(),
+            pid,
+            timeout,
+            action,
+            handle: handle.clone(),
+            expired_at_kick: None,
+        });
+        handle
+    }
+
+    #[allow(dead_code)]
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.len() != count
+    }
+
+    // Current PID watched under `name`; it changes when the process is restarted
+    #[allow(dead_code)]
+    pub fn pid(&self, name: &str) -> Option<u32> {
+        self.entries.iter().find(|entry| entry.name == name).and_then(|entry| entry.pid)
+    }
+
+    // Finds entries that were not kicked in time and carries out their actions.
+    // Call this periodically; a `Halt` expiry in the result means the caller
+    // should shut down.
+    pub fn check(&mut self, core: &mut CoreSystem, hal: &mut HAL) -> Vec<WatchdogExpiry> {
+        let now = self.now();
+        let mut expiries = Vec::new();
+        for entry in self.entries.iter_mut() {
+            let last_kick = entry.handle.last_kick();
+            let overdue = now.duration_since(last_kick);
+            if overdue <= entry.timeout || entry.expired_at_kick == Some(last_kick) {
+                continue;
+            }
+            entry.expired_at_kick = Some(last_kick);
+
+            let description = match entry.pid {
+                Some(pid) => format!("{} (PID {})", entry.name, pid),
+                None => entry.name.clone(),
+            };
+            logging::error(LOG_TARGET, &format!(
+                "Watchdog expired for {}: no kick for {:?} (timeout {:?}), action {:?}",
+                description, overdue, entry.timeout, entry.action
+            ));
+            let report = self.ipc.send_message(
+                entry.pid.unwrap_or(0),
+                self.mailbox,
+                format!("watchdog expired: {} action={:?}", description, entry.action),
+            );
+            if let Err(e) = report {
+                logging::warn(LOG_TARGET, &format!("Cannot report expiry of {}: {}", description, e));
+            }
+            expiries.push(WatchdogExpiry {
+                name: entry.name.clone(),
+                pid: entry.pid,
+                action: entry.action,
+                overdue,
+            });
+
+            match entry.action {
+                WatchdogAction::Restart => match entry.pid.map(|pid| core.restart_process(pid)) {
+                    Some(Ok(new_pid)) => {
+                        entry.pid = Some(new_pid);
+                        entry.handle.kick();
+                        entry.expired_at_kick = None;
+                    },
+                    Some(Err(e)) => {
+                        logging::error(LOG_TARGET, &format!("Restart of {} failed: {}", description, e));
+                        hal.safe_stop();
+                    },
+                    None => hal.safe_stop(),
+                },
+                WatchdogAction::SafeStop | WatchdogAction::Halt => hal.safe_stop(),
+            }
+        }
+        expiries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SimulatedClock;
+    use crate::core_system::{yield_now, DEFAULT_PRIORITY};
+    use crate::hal::Actuator;
+
+    #[test]
+    fn test_watchdog_restart() {
+        let mut core = CoreSystem::new();
+        let mut hal = HAL::new();
+        let ipc = IPC::new();
+        ipc.create_mailbox(0);
+        let clock = SimulatedClock::new();
+        let mut watchdog = Watchdog::new(ipc.clone(), 0);
+        watchdog.set_clock(Arc::new(clock.clone()));
+
+        let pid = core.spawn_restartable("planner", None, DEFAULT_PRIORITY, || async {
+            loop {
+                yield_now().await;
+            }
+        }).unwrap();
+        let handle = watchdog.register("planner", Some(pid), Duration::from_millis(20), WatchdogAction::Restart);
+
+        handle.kick();
+        assert!(watchdog.check(&mut core, &mut hal).is_empty());
+
+        clock.advance(Duration::from_millis(20));
+        assert!(watchdog.check(&mut core, &mut hal).is_empty());
+        clock.advance(Duration::from_millis(1));
+        let expiries = watchdog.check(&mut core, &mut hal);
+        assert_eq!(expiries.len(), 1);
+        assert_eq!(expiries[0].action, WatchdogAction::Restart);
+        let new_pid = watchdog.pid("planner").unwrap();
+        assert_ne!(new_pid, pid);
+        assert_eq!(core.exit_code(pid), Some(crate::core_system::RESTART_EXIT_CODE));
+        assert!(ipc.receive_message(0).unwrap().content.starts_with("watchdog expired: planner"));
+
+        // The restart counts as a kick
+        assert!(watchdog.check(&mut core, &mut hal).is_empty());
+
+        // A process that cannot be restarted gets the actuators safe-stopped instead
+        assert!(watchdog.unregister("planner"));
+        let manual = core.create_process_with("manual", None, DEFAULT_PRIORITY).unwrap();
+        watchdog.register("manual", Some(manual), Duration::from_millis(20), WatchdogAction::Restart);
+        hal.motor.write(0.5);
+        clock.advance(Duration::from_millis(21));
+        assert_eq!(watchdog.check(&mut core, &mut hal).len(), 1);
+        assert_eq!(hal.motor.speed(), 0.0);
+        assert_eq!(watchdog.pid("manual"), Some(manual));
+    }
+
+    #[test]
+    fn test_watchdog_safe_stop_fires_once() {
+        let mut core = CoreSystem::new();
+        let mut hal = HAL::new();
+        let clock = SimulatedClock::new();
+        let mut watchdog = Watchdog::new(IPC::new(), 0);
+        let handle = watchdog.register("main_loop", None, Duration::from_millis(10), WatchdogAction::Halt);
+        watchdog.set_clock(Arc::new(clock.clone()));
+
+        hal.motor.write(0.8);
+        clock.advance(Duration::from_millis(20));
+        let expiries = watchdog.check(&mut core, &mut hal);
+        assert_eq!(expiries.len(), 1);
+        assert_eq!(expiries[0].action, WatchdogAction::Halt);
+        assert_eq!(hal.motor.speed(), 0.0);
+        assert!(watchdog.check(&mut core, &mut hal).is_empty());
+
+        // A late kick re-arms the watchdog
+        handle.kick();
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(watchdog.check(&mut core, &mut hal).len(), 1);
+        assert!(watchdog.unregister("main_loop"));
+    }
+}